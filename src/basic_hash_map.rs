@@ -1,9 +1,33 @@
+use crate::bloom::BloomFilter;
+use crate::capacity_guard::{CapacityGuard, TableFull};
+use crate::diagnostics::Diagnostics;
+use crate::growth::{self, GROWTH_FACTOR};
+use crate::handle::Handle;
+use crate::insert_outcome::InsertOutcome;
+use crate::lifetime_stats::{LifetimeStats, StatsRecorder};
+use crate::map_delta::MapDelta;
+use crate::numeric;
+use crate::perfect_map::PerfectMap;
+use crate::rng::SplitMix64;
+use crate::robin_hood_hash_map::RobinHashMap;
+use crate::slot_view::SlotView;
+use crate::visit_action::VisitAction;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
 use std::hash::{DefaultHasher, Hash, Hasher};
-fn hash<K: Hash>(k: &K, modulus: u64) -> u64 {
+const MAX_LOAD_FACTOR: f64 = 0.7;
+const CACHE_LINE_BYTES: usize = 64;
+const MAX_PROBE_DOUBLINGS: usize = 8;
+fn capacity_for(len: usize) -> usize {
+    (len as f64 / MAX_LOAD_FACTOR) as usize + 1
+}
+fn hash_key<K: Hash>(k: &K) -> u64 {
     let mut hash_function = DefaultHasher::new();
     k.hash(&mut hash_function);
-    let result = hash_function.finish();
-    result % modulus
+    hash_function.finish()
+}
+fn hash<K: Hash>(k: &K, modulus: u64) -> u64 {
+    hash_key(k) % modulus
 }
 #[derive(Debug, Hash, Clone)]
 pub enum Slot<K, V> {
@@ -12,10 +36,16 @@ pub enum Slot<K, V> {
     Some((K, V)),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OpenHashMap<K, V> {
     array: Vec<Slot<K, V>>,
     capacity: usize,
+    len: usize,
+    stats: StatsRecorder,
+    bloom: Option<BloomFilter>,
+    generation: u64,
+    last_resize_moved: usize,
+    peak_len: usize,
 }
 
 impl<K, V> OpenHashMap<K, V>
@@ -28,51 +58,1394 @@ where
         for _ in 0..capacity {
             array.push(Slot::Empty);
         }
-        OpenHashMap { array, capacity }
+        OpenHashMap {
+            array,
+            capacity,
+            len: 0,
+            stats: StatsRecorder::default(),
+            bloom: None,
+            generation: 0,
+            last_resize_moved: 0,
+            peak_len: 0,
+        }
+    }
+    pub fn with_stats(mut self) -> Self {
+        self.stats = StatsRecorder::enabled();
+        self
+    }
+    pub fn lifetime_stats(&self) -> LifetimeStats {
+        self.stats.snapshot()
+    }
+    pub fn with_bloom(mut self) -> Self {
+        let mut bloom = BloomFilter::new(self.capacity);
+        for (key, _) in self.entries() {
+            bloom.insert(hash_key(key));
+        }
+        self.bloom = Some(bloom);
+        self
     }
     pub fn insert(&mut self, key: K, value: V) -> bool {
-        let mut index = hash(&key, self.capacity as u64) as usize;
-        let start_index = index;
-        loop {
+        self.insert_hashed(hash_key(&key), key, value)
+    }
+    fn insert_hashed(&mut self, hash: u64, key: K, value: V) -> bool {
+        if self.len >= self.resize_threshold()
+            && (self.capacity == 0 || self.probe(hash, &key).0.is_none())
+        {
+            self.grow();
+        }
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(hash);
+        }
+        let mut index = (hash % self.capacity as u64) as usize;
+        // A tombstone is only reusable once the rest of the chain is known not to hold the key
+        let mut free = None;
+        let mut probes = 0;
+        while probes < self.capacity {
+            probes += 1;
             match &self.array[index] {
-                Slot::Empty | Slot::Deleted => {
-                    self.array[index] = Slot::Some((key, value));
-                    return true;
-                }
                 Slot::Some((existing_key, _)) if existing_key == &key => {
                     self.array[index] = Slot::Some((key, value));
+                    self.stats.record_probes(probes);
                     return true;
                 }
-                _ => {
-                    index = (index + 1) % self.capacity;
-                    if index == start_index {
-                        return false;
+                Slot::Some(_) => {}
+                Slot::Deleted => {
+                    free.get_or_insert(index);
+                }
+                Slot::Empty => {
+                    free.get_or_insert(index);
+                    break;
+                }
+            }
+            index = (index + 1) % self.capacity;
+        }
+        self.stats.record_probes(probes);
+        let Some(index) = free else {
+            return false;
+        };
+        self.array[index] = Slot::Some((key, value));
+        self.len += 1;
+        self.peak_len = self.peak_len.max(self.len);
+        self.stats.record_insert();
+        true
+    }
+    fn insert_unique(&mut self, key: K, value: V) -> bool {
+        let hash = hash_key(&key);
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(hash);
+        }
+        let mut index = (hash % self.capacity as u64) as usize;
+        for probes in 1..=self.capacity {
+            if let Slot::Empty | Slot::Deleted = self.array[index] {
+                self.array[index] = Slot::Some((key, value));
+                self.len += 1;
+                self.peak_len = self.peak_len.max(self.len);
+                self.stats.record_probes(probes);
+                return true;
+            }
+            index = (index + 1) % self.capacity;
+        }
+        self.stats.record_probes(self.capacity);
+        false
+    }
+    // One walk: returns the slot holding `key`, or places make()'s value in the chain's first free slot
+    fn find_or_insert_with<F: FnOnce() -> V>(&mut self, hash: u64, key: K, make: F) -> usize {
+        if self.capacity > 0 {
+            let mut index = (hash % self.capacity as u64) as usize;
+            let mut free = None;
+            let mut probes = 0;
+            while probes < self.capacity {
+                probes += 1;
+                match &self.array[index] {
+                    Slot::Some((existing_key, _)) if existing_key == &key => {
+                        self.stats.record_probes(probes);
+                        return index;
+                    }
+                    Slot::Some(_) => {}
+                    Slot::Deleted => {
+                        free.get_or_insert(index);
+                    }
+                    Slot::Empty => {
+                        free.get_or_insert(index);
+                        break;
                     }
                 }
+                index = (index + 1) % self.capacity;
+            }
+            self.stats.record_probes(probes);
+            if let Some(index) = free
+                && self.len < self.resize_threshold()
+            {
+                self.stats.record_insert();
+                if let Some(bloom) = &mut self.bloom {
+                    bloom.insert(hash);
+                }
+                self.array[index] = Slot::Some((key, make()));
+                self.len += 1;
+                self.peak_len = self.peak_len.max(self.len);
+                return index;
             }
         }
+        self.grow();
+        self.find_or_insert_with(hash, key, make)
     }
     pub fn delete(&mut self, key: K) -> Slot<K, V> {
-        let index = hash(&key, self.capacity as u64) as usize;
-        let delete_value = self.array[index].clone();
-        self.array[index] = Slot::Deleted;
-        delete_value
+        self.stats.record_delete();
+        match self
+            .find_index(&key)
+            .and_then(|index| self.remove_at(index))
+        {
+            Some(entry) => Slot::Some(entry),
+            None => Slot::Empty,
+        }
     }
     pub fn find(&self, key: K) -> Slot<&K, &V> {
-        let mut index = hash(&key, self.capacity as u64) as usize;
-        loop {
+        self.stats.record_get();
+        let hash = hash_key(&key);
+        if let Some(bloom) = &self.bloom
+            && !bloom.may_contain(hash)
+        {
+            return Slot::Empty;
+        }
+        let mut index = (hash % self.capacity as u64) as usize;
+        // A table with no empty slot left would otherwise be walked forever
+        for probes in 1..=self.capacity {
             match &self.array[index] {
                 Slot::Some((k, v)) => {
                     if k == &key {
+                        self.stats.record_probes(probes);
                         return Slot::Some((k, v));
                     }
                     index = (index + 1) % self.capacity;
                 }
-                Slot::Empty => return Slot::Empty,
+                Slot::Empty => {
+                    self.stats.record_probes(probes);
+                    return Slot::Empty;
+                }
                 Slot::Deleted => {
                     index = (index + 1) % self.capacity;
                 }
             }
         }
+        self.stats.record_probes(self.capacity);
+        Slot::Empty
+    }
+    fn find_index(&self, key: &K) -> Option<usize> {
+        self.find_index_hashed(hash_key(key), key)
+    }
+    fn find_index_hashed(&self, hash: u64, key: &K) -> Option<usize> {
+        let (index, probes) = self.probe(hash, key);
+        self.stats.record_probes(probes);
+        index
+    }
+    // Returns the slot holding `key`, if any, and the number of slots examined
+    fn probe(&self, hash: u64, key: &K) -> (Option<usize>, usize) {
+        if let Some(bloom) = &self.bloom
+            && !bloom.may_contain(hash)
+        {
+            return (None, 0);
+        }
+        let mut index = (hash % self.capacity as u64) as usize;
+        for probes in 1..=self.capacity {
+            match &self.array[index] {
+                Slot::Some((k, _)) if k == key => return (Some(index), probes),
+                Slot::Empty => return (None, probes),
+                _ => index = (index + 1) % self.capacity,
+            }
+        }
+        (None, self.capacity)
+    }
+    pub fn apply_updates(&mut self, updates: &Self) {
+        for slot in &updates.array {
+            if let Slot::Some((key, value)) = slot
+                && let Some(index) = self.find_index(key)
+            {
+                self.array[index] = Slot::Some((key.clone(), *value));
+            }
+        }
+    }
+    fn displacement(&self, index: usize, key: &K) -> usize {
+        let home = hash(key, self.capacity as u64) as usize;
+        (index + self.capacity - home) % self.capacity
+    }
+    pub fn best_case_lookup(&self) -> Option<(&K, usize)> {
+        self.array
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Some((k, _)) => Some((k, self.displacement(index, k))),
+                _ => None,
+            })
+            .min_by_key(|&(_, distance)| distance)
+    }
+    pub fn shard(self, shards: usize) -> Vec<Self> {
+        assert!(shards > 0, "cannot shard a map into zero shards");
+        let mut maps: Vec<Self> = (0..shards)
+            .map(|_| Self::new(capacity_for(self.len / shards)))
+            .collect();
+        for slot in self.array {
+            if let Slot::Some((key, value)) = slot {
+                let target = hash(&key, shards as u64) as usize;
+                maps[target].reserve(1);
+                maps[target].insert(key, value);
+            }
+        }
+        maps
+    }
+    fn lookup(&self, key: &K) -> Option<&V> {
+        self.get_prehashed(hash_key(key), key)
+    }
+    // The lookup behind every public get-style read, so each one counts once in get_count
+    fn recorded_lookup(&self, key: &K) -> Option<&V> {
+        self.stats.record_get();
+        self.lookup(key)
+    }
+    pub fn get_or<'a>(&'a self, key: &K, default: &'a V) -> &'a V {
+        self.recorded_lookup(key).unwrap_or(default)
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity as f64
+    }
+    fn tombstones(&self) -> usize {
+        self.array
+            .iter()
+            .filter(|slot| matches!(slot, Slot::Deleted))
+            .count()
+    }
+    pub fn is_healthy(&self) -> bool {
+        // Tombstones lengthen probe chains just like live entries
+        let occupied = self.len + self.tombstones();
+        (occupied as f64 / self.capacity as f64) < MAX_LOAD_FACTOR
+    }
+    fn remove_at(&mut self, index: usize) -> Option<(K, V)> {
+        match std::mem::replace(&mut self.array[index], Slot::Deleted) {
+            Slot::Some(entry) => {
+                self.len -= 1;
+                Some(entry)
+            }
+            other => {
+                self.array[index] = other;
+                None
+            }
+        }
+    }
+    pub fn move_entry_to(&mut self, key: &K, dest: &mut Self) -> bool {
+        match self.find_index(key).and_then(|index| self.remove_at(index)) {
+            Some((key, value)) => {
+                dest.insert(key, value);
+                true
+            }
+            None => false,
+        }
+    }
+    fn entries(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.array.iter().filter_map(|slot| match slot {
+            Slot::Some((k, v)) => Some((k, v)),
+            _ => None,
+        })
+    }
+    pub fn count_values<F: FnMut(&V) -> bool>(&self, mut pred: F) -> usize {
+        self.entries().filter(|(_, value)| pred(value)).count()
+    }
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.find_index(key)?;
+        match &mut self.array[index] {
+            Slot::Some((_, value)) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn get_mut_or_panic(&mut self, key: &K) -> &mut V
+    where
+        K: std::fmt::Debug,
+    {
+        match self.get_mut(key) {
+            Some(value) => value,
+            None => panic!("key {:?} not found in OpenHashMap", key),
+        }
+    }
+    pub fn fill_histogram(&self, buckets: usize) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 {
+            return histogram;
+        }
+        for (index, slot) in self.array.iter().enumerate() {
+            if let Slot::Some(_) = slot {
+                histogram[index * buckets / self.capacity] += 1;
+            }
+        }
+        histogram
+    }
+    pub fn take(&mut self, key: &K) -> Option<V>
+    where
+        V: Default,
+    {
+        self.get_mut(key).map(std::mem::take)
+    }
+    pub fn prehash(keys: &[K]) -> Vec<u64> {
+        keys.iter().map(hash_key).collect()
+    }
+    pub fn get_prehashed(&self, hash: u64, key: &K) -> Option<&V> {
+        match &self.array[self.find_index_hashed(hash, key)?] {
+            Slot::Some((_, value)) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn dedup(&mut self) -> usize {
+        let mut seen = HashSet::new();
+        let duplicates: Vec<usize> = self
+            .array
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Some((k, _)) if !seen.insert(k) => Some(index),
+                _ => None,
+            })
+            .collect();
+        for &index in &duplicates {
+            self.remove_at(index);
+        }
+        duplicates.len()
+    }
+    pub fn total_probe_cost(&self) -> usize {
+        self.array
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| match slot {
+                Slot::Some((k, _)) => self.displacement(index, k) + 1,
+                _ => 0,
+            })
+            .sum()
+    }
+    pub fn from_sorted_unique(pairs: &[(K, V)]) -> Self
+    where
+        K: Ord,
+    {
+        debug_assert!(
+            pairs.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "from_sorted_unique requires keys sorted in strictly ascending order"
+        );
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (key, value) in pairs {
+            map.insert_unique(key.clone(), *value);
+        }
+        map
+    }
+    pub fn filter_keys<F: FnMut(&K) -> bool>(&self, mut pred: F) -> impl Iterator<Item = (&K, &V)> {
+        self.entries().filter(move |(key, _)| pred(key))
+    }
+    pub fn get_with_index(&self, key: &K) -> Option<(usize, &V)> {
+        let index = self.find_index(key)?;
+        match &self.array[index] {
+            Slot::Some((_, value)) => Some((index, value)),
+            _ => None,
+        }
+    }
+    fn rebuild(&mut self, capacity: usize) {
+        if capacity != self.capacity {
+            self.stats.record_resize();
+            self.last_resize_moved = self.len;
+        }
+        let old = std::mem::replace(
+            &mut self.array,
+            (0..capacity).map(|_| Slot::Empty).collect(),
+        );
+        // Rehashing is bookkeeping, not caller work, so its probes stay out of the stats
+        let stats = std::mem::take(&mut self.stats);
+        self.capacity = capacity;
+        self.len = 0;
+        self.generation += 1;
+        if self.bloom.is_some() {
+            self.bloom = Some(BloomFilter::new(capacity));
+        }
+        for slot in old {
+            if let Slot::Some((key, value)) = slot {
+                self.insert_unique(key, value);
+            }
+        }
+        self.stats = stats;
+    }
+    fn max_displacement(&self) -> usize {
+        self.array
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Some((k, _)) => Some(self.displacement(index, k)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+    // Doubles until no entry is displaced more than `limit` slots, at most MAX_PROBE_DOUBLINGS times.
+    // Growth cannot separate keys with equal hashes, so it also stops once a doubling no longer helps;
+    // the limit may then still be exceeded
+    pub fn enforce_max_probe(&mut self, limit: usize) -> bool {
+        let mut worst = self.max_displacement();
+        let mut doublings = 0;
+        while worst > limit && doublings < MAX_PROBE_DOUBLINGS {
+            self.rebuild(self.capacity * 2);
+            doublings += 1;
+            let shorter = self.max_displacement();
+            if shorter >= worst {
+                break;
+            }
+            worst = shorter;
+        }
+        doublings > 0
+    }
+    fn reserve(&mut self, additional: usize) {
+        let needed = capacity_for(self.len + additional);
+        if needed > self.capacity {
+            self.rebuild(needed.max(self.capacity * 2));
+        }
+    }
+    pub fn delta_since(&self, base: &Self) -> MapDelta<K, V> {
+        let mut delta = MapDelta::default();
+        for (key, value) in self.entries() {
+            match base.lookup(key) {
+                None => delta.added.push((key.clone(), *value)),
+                Some(old) if old != value => delta.changed.push((key.clone(), *value)),
+                Some(_) => {}
+            }
+        }
+        for (key, _) in base.entries() {
+            if self.find_index(key).is_none() {
+                delta.removed.push(key.clone());
+            }
+        }
+        delta
+    }
+    pub fn apply_delta(&mut self, mut delta: MapDelta<K, V>) {
+        for key in &delta.removed {
+            if let Some(index) = self.find_index(key) {
+                self.remove_at(index);
+            }
+        }
+        for (key, value) in delta.changed {
+            match self.get_mut(&key) {
+                Some(existing) => *existing = value,
+                None => delta.added.push((key, value)),
+            }
+        }
+        self.reserve(delta.added.len());
+        for (key, value) in delta.added {
+            self.insert(key, value);
+        }
+    }
+    pub fn get_first(&self, keys: &[K]) -> Option<(&K, &V)> {
+        let index = keys.iter().find_map(|key| self.find_index(key))?;
+        match &self.array[index] {
+            Slot::Some((k, v)) => Some((k, v)),
+            _ => None,
+        }
+    }
+    // Sized for the distinct keys, so repeats in `keys` do not inflate the table. Slots have nowhere
+    // to cache a hash, so each key is hashed again when its value is inserted
+    pub fn prepare_for(keys: &[K]) -> Self {
+        let distinct: HashSet<&K> = keys.iter().collect();
+        Self::new(capacity_for(distinct.len()))
+    }
+    pub fn clone_with_capacity(&self, capacity: usize) -> Self {
+        assert!(
+            capacity >= self.len && capacity > 0,
+            "capacity {} cannot hold {} entries",
+            capacity,
+            self.len
+        );
+        let mut copy = self.clone();
+        copy.rebuild(capacity);
+        copy
+    }
+    pub fn raw_iter(&self) -> impl Iterator<Item = SlotView<'_, K, V>> {
+        self.array
+            .iter()
+            .enumerate()
+            .map(|(index, slot)| match slot {
+                Slot::Some((k, v)) => SlotView::Occupied(k, v, self.displacement(index, k)),
+                Slot::Deleted => SlotView::Deleted,
+                Slot::Empty => SlotView::Empty,
+            })
+    }
+    pub fn swap_values(&mut self, a: &K, b: &K) -> bool {
+        let (Some(i), Some(j)) = (self.find_index(a), self.find_index(b)) else {
+            return false;
+        };
+        if i == j {
+            return false;
+        }
+        let (low, high) = self.array.split_at_mut(i.max(j));
+        match (&mut low[i.min(j)], &mut high[0]) {
+            (Slot::Some((_, first)), Slot::Some((_, second))) => {
+                std::mem::swap(first, second);
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn top_n(&self, n: usize) -> Vec<(&K, &V)>
+    where
+        V: Ord,
+    {
+        if n == 0 {
+            return Vec::new();
+        }
+        // Min-heap of the n largest seen so far, with the slot index as tie-breaker
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (index, slot) in self.array.iter().enumerate() {
+            if let Slot::Some((_, value)) = slot {
+                heap.push(Reverse((value, index)));
+                if heap.len() > n {
+                    heap.pop();
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .filter_map(|Reverse((_, index))| match &self.array[index] {
+                Slot::Some((k, v)) => Some((k, v)),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn vacuum(&mut self) {
+        self.rebuild(self.capacity);
+    }
+    pub fn needs_vacuum(&self) -> bool {
+        let occupied = (self.len + self.tombstones()) as f64 / self.capacity as f64;
+        occupied > MAX_LOAD_FACTOR && self.load_factor() < MAX_LOAD_FACTOR / 2.0
+    }
+    pub fn insert_handle(&mut self, key: K, value: V) -> Handle<K> {
+        let hash = hash_key(&key);
+        self.reserve(1);
+        self.insert(key.clone(), value);
+        let index = self.find_index_hashed(hash, &key).unwrap();
+        Handle {
+            key,
+            hash,
+            index,
+            generation: self.generation,
+        }
+    }
+    pub fn get_by_handle(&self, handle: &Handle<K>) -> Option<&V> {
+        if handle.generation == self.generation
+            && let Some(Slot::Some((k, v))) = self.array.get(handle.index)
+            && k == &handle.key
+        {
+            return Some(v);
+        }
+        self.get_prehashed(handle.hash, &handle.key)
+    }
+    fn home_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.entries()
+            .map(|(key, _)| hash(key, self.capacity as u64) as usize)
+    }
+    pub fn home_slot_entropy(&self) -> f64 {
+        let mut counts = vec![0usize; self.capacity];
+        for home in self.home_indices() {
+            counts[home] += 1;
+        }
+        let total = self.len as f64;
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+    pub fn vacuum_range(&mut self, start: usize, count: usize) -> usize {
+        if self.capacity == 0 {
+            return 0;
+        }
+        let start = start % self.capacity;
+        let count = count.min(self.capacity);
+        let mut emptied = 0;
+        for offset in 0..count {
+            let index = (start + offset) % self.capacity;
+            if let Slot::Deleted = self.array[index] {
+                self.array[index] = Slot::Empty;
+                emptied += 1;
+            }
+        }
+        if emptied == 0 {
+            return 0;
+        }
+        // Emptying a tombstone can cut the probe chain of any entry behind it, so re-place, in probe
+        // order, every entry from the start of the surrounding cluster up to the next empty slot past
+        // the window. Each entry lands at or before its old slot, so nothing outside that run moves
+        let mut first = start;
+        for _ in 1..self.capacity {
+            let previous = (first + self.capacity - 1) % self.capacity;
+            if let Slot::Empty = self.array[previous] {
+                break;
+            }
+            first = previous;
+        }
+        let mut length =
+            ((start + self.capacity - first) % self.capacity + count).min(self.capacity);
+        while length < self.capacity
+            && !matches!(self.array[(first + length) % self.capacity], Slot::Empty)
+        {
+            length += 1;
+        }
+        let mut tombstones_left = 0;
+        let mut entries = Vec::new();
+        for offset in 0..length {
+            let index = (first + offset) % self.capacity;
+            match std::mem::replace(&mut self.array[index], Slot::Empty) {
+                Slot::Some(entry) => entries.push(entry),
+                Slot::Deleted => {
+                    self.array[index] = Slot::Deleted;
+                    tombstones_left += 1;
+                }
+                Slot::Empty => {}
+            }
+        }
+        self.len -= entries.len();
+        for (key, value) in entries {
+            self.insert_unique(key, value);
+        }
+        // Re-placed entries may also have filled tombstones outside the window
+        let refilled = tombstones_left
+            - (0..length)
+                .filter(|offset| {
+                    matches!(self.array[(first + offset) % self.capacity], Slot::Deleted)
+                })
+                .count();
+        emptied + refilled
+    }
+    pub fn invert(&self) -> RobinHashMap<V, Vec<K>>
+    where
+        V: Hash,
+    {
+        let mut inverted: RobinHashMap<V, Vec<K>> = RobinHashMap::new(self.capacity);
+        for (key, value) in self.entries() {
+            match inverted.get_mut(value) {
+                Some(keys) => keys.push(key.clone()),
+                None => {
+                    inverted.insert(*value, vec![key.clone()]);
+                }
+            }
+        }
+        inverted
+    }
+    pub fn retain_visit<F: FnMut(&K, &V) -> VisitAction>(&mut self, mut f: F) {
+        for index in 0..self.capacity {
+            let action = match &self.array[index] {
+                Slot::Some((k, v)) => f(k, v),
+                _ => continue,
+            };
+            match action {
+                VisitAction::Keep => {}
+                VisitAction::Remove => {
+                    self.remove_at(index);
+                }
+                VisitAction::Stop => return,
+            }
+        }
+    }
+    pub fn resize_threshold(&self) -> usize {
+        growth::threshold(self.capacity, MAX_LOAD_FACTOR)
+    }
+    fn grow(&mut self) {
+        self.rebuild(growth::grown_capacity(
+            self.capacity,
+            self.len,
+            GROWTH_FACTOR,
+            MAX_LOAD_FACTOR,
+        ));
+    }
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let live = self.entries().count();
+        if live != self.len {
+            return Err(format!("len is {} but {} slots are live", self.len, live));
+        }
+        for (index, slot) in self.array.iter().enumerate() {
+            let Slot::Some((key, _)) = slot else {
+                continue;
+            };
+            let home = hash(key, self.capacity as u64) as usize;
+            for offset in 0..self.displacement(index, key) {
+                let between = (home + offset) % self.capacity;
+                if let Slot::Empty = self.array[between] {
+                    return Err(format!(
+                        "slot {} is unreachable from home slot {}: slot {} is empty",
+                        index, home, between
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn verified_clone(&self) -> Self {
+        let copy = self.clone();
+        if let Err(violation) = copy.check_invariants() {
+            panic!("cloned OpenHashMap is inconsistent: {}", violation);
+        }
+        copy
+    }
+    pub fn clear(&mut self) {
+        self.array.iter_mut().for_each(|slot| *slot = Slot::Empty);
+        self.len = 0;
+        if self.bloom.is_some() {
+            self.bloom = Some(BloomFilter::new(self.capacity));
+        }
+    }
+    pub fn replace_all(&mut self, pairs: impl IntoIterator<Item = (K, V)>) {
+        let pairs: Vec<(K, V)> = pairs.into_iter().collect();
+        self.clear();
+        self.reserve(pairs.len());
+        for (key, value) in pairs {
+            self.insert(key, value);
+        }
+    }
+    pub fn last_resize_moved(&self) -> usize {
+        self.last_resize_moved
+    }
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        self.lookup(key).map(f)
+    }
+    pub fn compact_tombstones(&mut self) {
+        let Some(empty) = self
+            .array
+            .iter()
+            .position(|slot| matches!(slot, Slot::Empty))
+        else {
+            self.vacuum_range(0, self.capacity);
+            return;
+        };
+        // Walk once around the table from an empty slot, collecting clusters that hold tombstones
+        let mut clusters = Vec::new();
+        let mut cluster_start = None;
+        let mut has_tombstone = false;
+        for offset in 1..=self.capacity {
+            let index = (empty + offset) % self.capacity;
+            match self.array[index] {
+                Slot::Empty => {
+                    if let Some(start) = cluster_start.take()
+                        && has_tombstone
+                    {
+                        clusters.push((start, (index + self.capacity - start) % self.capacity));
+                    }
+                    has_tombstone = false;
+                }
+                Slot::Deleted => {
+                    cluster_start.get_or_insert(index);
+                    has_tombstone = true;
+                }
+                Slot::Some(_) => {
+                    cluster_start.get_or_insert(index);
+                }
+            }
+        }
+        for (start, count) in clusters {
+            self.vacuum_range(start, count);
+        }
+    }
+    pub fn weighted_probe_cost(&self, query_counts: &[(K, usize)]) -> f64 {
+        let total: usize = query_counts.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let weighted: usize = query_counts
+            .iter()
+            .map(|(key, count)| self.probe(hash_key(key), key).1 * count)
+            .sum();
+        weighted as f64 / total as f64
+    }
+    pub fn from_prehashed(pairs: impl IntoIterator<Item = (u64, K, V)>) -> Self {
+        let pairs: Vec<(u64, K, V)> = pairs.into_iter().collect();
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (hash, key, value) in pairs {
+            map.insert_hashed(hash, key, value);
+        }
+        map
+    }
+    pub fn occupancy_rle(&self) -> Vec<(bool, usize)> {
+        let mut runs: Vec<(bool, usize)> = Vec::new();
+        for slot in &self.array {
+            let occupied = matches!(slot, Slot::Some(_));
+            match runs.last_mut() {
+                Some((state, length)) if *state == occupied => *length += 1,
+                _ => runs.push((occupied, 1)),
+            }
+        }
+        runs
+    }
+    pub fn merge_max<F: FnMut(&V, &V) -> Ordering>(&mut self, other: Self, mut cmp: F) {
+        for slot in other.array {
+            let Slot::Some((key, value)) = slot else {
+                continue;
+            };
+            match self.get_mut(&key) {
+                Some(existing) => {
+                    if cmp(&value, existing) == Ordering::Greater {
+                        *existing = value;
+                    }
+                }
+                None => {
+                    self.reserve(1);
+                    self.insert(key, value);
+                }
+            }
+        }
+    }
+    pub fn colliding_keys_with(&self, other: &Self) -> Vec<&K> {
+        let occupied_homes: HashSet<usize> = other.home_indices().collect();
+        self.entries()
+            .map(|(key, _)| key)
+            .filter(|key| occupied_homes.contains(&(hash(key, other.capacity as u64) as usize)))
+            .collect()
+    }
+    pub fn into_iter_by_distance(self) -> impl Iterator<Item = (K, V)> {
+        let capacity = self.capacity;
+        let mut entries: Vec<(usize, K, V)> = self
+            .array
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                Slot::Some((key, value)) => {
+                    let home = hash(&key, capacity as u64) as usize;
+                    Some(((index + capacity - home) % capacity, key, value))
+                }
+                _ => None,
+            })
+            .collect();
+        entries.sort_by_key(|&(distance, _, _)| distance);
+        entries.into_iter().map(|(_, key, value)| (key, value))
+    }
+    pub fn verify_after_resize(&self) -> Result<(), String> {
+        for (index, slot) in self.array.iter().enumerate() {
+            let Slot::Some((key, _)) = slot else {
+                continue;
+            };
+            if self.probe(hash_key(key), key).0 != Some(index) {
+                return Err(format!(
+                    "slot {} is not reachable by probing from home slot {} at capacity {}",
+                    index,
+                    hash(key, self.capacity as u64),
+                    self.capacity
+                ));
+            }
+        }
+        Ok(())
+    }
+    pub fn to_dot(&self) -> String
+    where
+        K: std::fmt::Debug,
+    {
+        let mut dot = String::from("digraph OpenHashMap {\n");
+        for (index, slot) in self.array.iter().enumerate() {
+            let Slot::Some((key, _)) = slot else {
+                continue;
+            };
+            dot.push_str(&format!(
+                "    slot{} [label=\"{}: {}\"];\n",
+                index,
+                index,
+                format!("{:?}", key).replace('"', "\\\"")
+            ));
+            let distance = self.displacement(index, key);
+            if distance > 0 {
+                let home = hash(key, self.capacity as u64);
+                dot.push_str(&format!(
+                    "    slot{} -> slot{} [label=\"{}\"];\n",
+                    home, index, distance
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+    pub fn insert_batch(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Vec<InsertOutcome<V>> {
+        let pairs: Vec<(K, V)> = pairs.into_iter().collect();
+        self.reserve(pairs.len());
+        let mut outcomes = Vec::with_capacity(pairs.len());
+        for (key, value) in pairs {
+            match self.get_mut(&key) {
+                Some(existing) => {
+                    outcomes.push(InsertOutcome::Updated(std::mem::replace(existing, value)))
+                }
+                None => {
+                    self.insert(key, value);
+                    outcomes.push(InsertOutcome::Inserted);
+                }
+            }
+        }
+        outcomes
+    }
+    pub fn resizes_for(
+        initial_capacity: usize,
+        inserts: usize,
+        growth_factor: f64,
+        max_load: f64,
+    ) -> usize {
+        growth::resizes_for(initial_capacity, inserts, growth_factor, max_load)
+    }
+    pub fn home_hit_ratio(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        let at_home = self
+            .array
+            .iter()
+            .enumerate()
+            .filter(|(index, slot)| matches!(slot, Slot::Some((k, _)) if self.displacement(*index, k) == 0))
+            .count();
+        at_home as f64 / self.len as f64
+    }
+    pub fn primary_cluster_lengths(&self) -> Vec<usize> {
+        let Some(empty) = self
+            .array
+            .iter()
+            .position(|slot| matches!(slot, Slot::Empty))
+        else {
+            return vec![self.capacity];
+        };
+        // Tombstones still extend probe sequences, so they count towards a cluster
+        let mut lengths = Vec::new();
+        let mut run = 0;
+        for offset in 1..=self.capacity {
+            match self.array[(empty + offset) % self.capacity] {
+                Slot::Empty if run > 0 => {
+                    lengths.push(run);
+                    run = 0;
+                }
+                Slot::Empty => {}
+                _ => run += 1,
+            }
+        }
+        lengths
+    }
+    pub fn cluster_size_variance(&self) -> f64 {
+        let lengths = self.primary_cluster_lengths();
+        if lengths.is_empty() {
+            return 0.0;
+        }
+        let count = lengths.len() as f64;
+        let mean = lengths.iter().sum::<usize>() as f64 / count;
+        lengths
+            .iter()
+            .map(|&length| (length as f64 - mean).powi(2))
+            .sum::<f64>()
+            / count
+    }
+    pub fn cluster_of(&self, key: &K) -> Vec<(&K, &V)> {
+        let index = self
+            .find_index(key)
+            .unwrap_or_else(|| hash(key, self.capacity as u64) as usize);
+        if let Slot::Empty = self.array[index] {
+            return Vec::new();
+        }
+        let mut start = index;
+        for _ in 1..self.capacity {
+            let previous = (start + self.capacity - 1) % self.capacity;
+            if let Slot::Empty = self.array[previous] {
+                break;
+            }
+            start = previous;
+        }
+        (0..self.capacity)
+            .map(|offset| &self.array[(start + offset) % self.capacity])
+            .take_while(|slot| !matches!(slot, Slot::Empty))
+            .filter_map(|slot| match slot {
+                Slot::Some((k, v)) => Some((k, v)),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn into_perfect(self) -> PerfectMap<K, V> {
+        PerfectMap::build(
+            self.array
+                .into_iter()
+                .filter_map(|slot| match slot {
+                    Slot::Some(pair) => Some(pair),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+    pub fn peak_len(&self) -> usize {
+        self.peak_len
+    }
+    // Falls back to a full scan when the key is absent, so this is O(n)
+    pub fn get_closest(&self, key: &K) -> Option<(&K, &V)>
+    where
+        K: Ord + Copy + TryInto<i128> + TryInto<u128>,
+    {
+        if let Some(index) = self.find_index(key)
+            && let Slot::Some((k, v)) = &self.array[index]
+        {
+            return Some((k, v));
+        }
+        self.entries()
+            .min_by_key(|&(candidate, _)| (numeric::integer_distance(*candidate, *key), *candidate))
+    }
+    pub fn value_size_histogram<F: Fn(&V) -> usize>(
+        &self,
+        size_of: F,
+        buckets: &[usize],
+    ) -> Vec<usize> {
+        debug_assert!(
+            buckets.windows(2).all(|pair| pair[0] < pair[1]),
+            "value_size_histogram requires bucket bounds in strictly ascending order"
+        );
+        // One bin per inclusive upper bound, plus a final bin for anything larger
+        let mut histogram = vec![0; buckets.len() + 1];
+        for (_, value) in self.entries() {
+            let size = size_of(value);
+            histogram[buckets.partition_point(|&bound| bound < size)] += 1;
+        }
+        histogram
+    }
+    pub fn fold<B, F: FnMut(B, &K, &V) -> B>(&self, init: B, mut f: F) -> B {
+        self.entries()
+            .fold(init, |acc, (key, value)| f(acc, key, value))
+    }
+    pub fn summary(&self) -> String {
+        format!(
+            "OpenHashMap len={} capacity={} load={:.2}",
+            self.len,
+            self.capacity,
+            self.load_factor()
+        )
+    }
+    pub fn keys_affected_by_shrink(&self, new_capacity: usize) -> usize {
+        assert!(new_capacity > 0, "cannot rehash into a zero-capacity table");
+        self.entries()
+            .filter(|(key, _)| {
+                let hash = hash_key(key);
+                hash % self.capacity as u64 != hash % new_capacity as u64
+            })
+            .count()
+    }
+    pub fn from_iter_with<I, F>(iter: I, mut merge: F) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(V, V) -> V,
+    {
+        let pairs: Vec<(K, V)> = iter.into_iter().collect();
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (key, value) in pairs {
+            match map.get_mut(&key) {
+                Some(existing) => *existing = merge(*existing, value),
+                None => {
+                    map.insert(key, value);
+                }
+            }
+        }
+        map
+    }
+    pub fn hash_of(&self, key: &K) -> u64 {
+        hash_key(key)
+    }
+    pub fn get_each_mut(&mut self, keys: &[K]) -> Vec<Option<&mut V>> {
+        let indices: Vec<Option<usize>> = keys.iter().map(|key| self.find_index(key)).collect();
+        // Taking each reference out of the slot list leaves None behind for repeated keys
+        let mut values: Vec<Option<&mut V>> = self
+            .array
+            .iter_mut()
+            .map(|slot| match slot {
+                Slot::Some((_, value)) => Some(value),
+                _ => None,
+            })
+            .collect();
+        indices
+            .into_iter()
+            .map(|index| index.and_then(|index| values[index].take()))
+            .collect()
+    }
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut displacement_histogram = Vec::new();
+        for (index, slot) in self.array.iter().enumerate() {
+            let Slot::Some((key, _)) = slot else {
+                continue;
+            };
+            let displacement = self.displacement(index, key);
+            if displacement >= displacement_histogram.len() {
+                displacement_histogram.resize(displacement + 1, 0);
+            }
+            displacement_histogram[displacement] += 1;
+        }
+        let violations = [self.check_invariants(), self.verify_after_resize()]
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        Diagnostics {
+            len: self.len,
+            capacity: self.capacity,
+            load_factor: self.load_factor(),
+            tombstones: self.tombstones(),
+            displacement_histogram,
+            stats: self.lifetime_stats(),
+            home_hit_ratio: self.home_hit_ratio(),
+            violations,
+        }
+    }
+    pub fn map_keys<K2, F>(self, mut f: F) -> RobinHashMap<K2, V>
+    where
+        K2: Hash + Eq + Clone,
+        F: FnMut(K) -> K2,
+    {
+        let mut mapped = RobinHashMap::new(capacity_for(self.len));
+        for slot in self.array {
+            if let Slot::Some((key, value)) = slot {
+                mapped.insert(f(key), value);
+            }
+        }
+        mapped
+    }
+    // Nearest-rank percentile of displacement with p as a fraction in 0.0..=1.0; 0 for an empty map
+    pub fn displacement_percentile(&self, p: f64) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+        let mut histogram = vec![0usize; self.capacity];
+        for (index, slot) in self.array.iter().enumerate() {
+            if let Slot::Some((key, _)) = slot {
+                histogram[self.displacement(index, key)] += 1;
+            }
+        }
+        let rank = ((p.clamp(0.0, 1.0) * self.len as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (displacement, &count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return displacement;
+            }
+        }
+        self.max_displacement()
+    }
+    pub fn increment(&mut self, key: K, by: V) -> &V
+    where
+        V: std::ops::AddAssign + Default,
+    {
+        let index = self.find_or_insert_with(hash_key(&key), key, V::default);
+        let Slot::Some((_, value)) = &mut self.array[index] else {
+            unreachable!("find_or_insert_with returned a slot without an entry");
+        };
+        *value += by;
+        value
+    }
+    pub fn balance_ratio(&self) -> f64 {
+        let midpoint = self.capacity / 2;
+        let lower = self.home_indices().filter(|&home| home < midpoint).count();
+        let upper = self.len - lower;
+        match (lower, upper) {
+            (0, 0) => 1.0,
+            (_, 0) => f64::INFINITY,
+            _ => lower as f64 / upper as f64,
+        }
+    }
+    pub fn get_cloned(&self, key: &K) -> Option<V> {
+        self.recorded_lookup(key).cloned()
+    }
+    // Slots per 64-byte cache line, or 1 when a single slot spans more than a line
+    pub fn cache_alignment_unit() -> usize {
+        (CACHE_LINE_BYTES / std::mem::size_of::<Slot<K, V>>().max(1)).max(1)
+    }
+    pub fn reserve_cache_aligned(&mut self, additional: usize) {
+        let unit = Self::cache_alignment_unit();
+        let needed = capacity_for(self.len + additional);
+        if needed > self.capacity {
+            self.rebuild(needed.max(self.capacity * 2).next_multiple_of(unit));
+        } else if !self.capacity.is_multiple_of(unit) {
+            self.rebuild(self.capacity.next_multiple_of(unit));
+        }
+    }
+    pub fn iter_shuffled(&self, seed: u64) -> impl Iterator<Item = (&K, &V)> {
+        let mut indices: Vec<usize> = (0..self.capacity)
+            .filter(|&index| matches!(self.array[index], Slot::Some(_)))
+            .collect();
+        SplitMix64::new(seed).shuffle(&mut indices);
+        indices
+            .into_iter()
+            .filter_map(|index| match &self.array[index] {
+                Slot::Some((key, value)) => Some((key, value)),
+                _ => None,
+            })
+    }
+    pub fn key_jaccard(&self, other: &Self) -> f64 {
+        let shared = self
+            .entries()
+            .filter(|(key, _)| other.find_index(key).is_some())
+            .count();
+        let union = self.len + other.len - shared;
+        if union == 0 {
+            return 1.0;
+        }
+        shared as f64 / union as f64
+    }
+    // Only a hint: a no-op on targets without a prefetch intrinsic
+    pub fn prefetch(&self, key: &K) {
+        if self.capacity == 0 {
+            return;
+        }
+        let slot = self
+            .array
+            .as_ptr()
+            .wrapping_add(hash(key, self.capacity as u64) as usize);
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+            // SAFETY: prefetch never faults, even on an invalid address, and SSE is baseline on x86_64
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(slot as *const i8) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = slot;
+    }
+    pub fn export_keys(&self) -> Vec<K> {
+        self.entries().map(|(key, _)| key.clone()).collect()
+    }
+    pub fn retain_only_keys(&mut self, keys: &[K]) {
+        let keep: HashSet<&K> = keys.iter().collect();
+        self.retain_visit(|key, _| {
+            if keep.contains(key) {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        });
+    }
+    // Counts the slot array only; heap memory owned by keys and values is unaffected by shrinking
+    pub fn shrink_savings_bytes(&self) -> usize {
+        self.capacity.saturating_sub(capacity_for(self.len)) * std::mem::size_of::<Slot<K, V>>()
+    }
+    pub fn to_canonical(&self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(K, V)> = self
+            .entries()
+            .map(|(key, value)| (key.clone(), *value))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+    // Inserting in key order at a capacity fixed by the length gives the same layout on every run
+    pub fn from_canonical(mut entries: Vec<(K, V)>) -> Self
+    where
+        K: Ord,
+    {
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut map = Self::new(capacity_for(entries.len()));
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+    // Runs are reported in slot order, so a cluster wrapping past the last slot appears as two runs
+    pub fn occupied_runs(&self) -> impl Iterator<Item = (usize, Vec<(&K, &V)>)> {
+        let mut runs: Vec<(usize, Vec<(&K, &V)>)> = Vec::new();
+        let mut current: Option<(usize, Vec<(&K, &V)>)> = None;
+        for (index, slot) in self.array.iter().enumerate() {
+            match (slot, &mut current) {
+                (Slot::Some((key, value)), Some((_, entries))) => entries.push((key, value)),
+                (Slot::Some((key, value)), None) => current = Some((index, vec![(key, value)])),
+                _ => runs.extend(current.take()),
+            }
+        }
+        runs.extend(current);
+        runs.into_iter()
+    }
+    pub fn get_or_compute<F: FnOnce() -> V>(&mut self, key: K, compute: F) -> &V {
+        let index = self.find_or_insert_with(hash_key(&key), key, compute);
+        let Slot::Some((_, value)) = &self.array[index] else {
+            unreachable!("find_or_insert_with returned a slot without an entry");
+        };
+        value
+    }
+    pub fn predict_worst_probes_after_resize(&self, new_capacity: usize) -> usize {
+        assert!(
+            new_capacity >= self.len && new_capacity > 0,
+            "capacity {} cannot hold {} entries",
+            new_capacity,
+            self.len
+        );
+        // Replays rebuild's insertion order, tracking only which slots are taken
+        let mut occupied = vec![false; new_capacity];
+        let mut worst = 0;
+        for (key, _) in self.entries() {
+            let mut index = hash(key, new_capacity as u64) as usize;
+            let mut probes = 1;
+            while occupied[index] {
+                index = (index + 1) % new_capacity;
+                probes += 1;
+            }
+            occupied[index] = true;
+            worst = worst.max(probes);
+        }
+        worst
+    }
+    pub fn retain_counting<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let before = self.len;
+        self.retain_visit(|key, value| {
+            if f(key, value) {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        });
+        before - self.len
+    }
+    pub fn union_with<F: FnMut(&V, &V) -> V>(&self, other: &Self, mut combine: F) -> Self {
+        let mut union = Self::new(capacity_for(self.len + other.len));
+        for (key, value) in self.entries() {
+            union.insert(key.clone(), *value);
+        }
+        for (key, value) in other.entries() {
+            match union.get_mut(key) {
+                Some(existing) => *existing = combine(existing, value),
+                None => {
+                    union.insert(key.clone(), *value);
+                }
+            }
+        }
+        union
+    }
+    // Long probes below the max load point to colliding keys rather than a full table. There is no salt
+    // to change here, so recover by moving the data into a reseeded RobinHashMap
+    pub fn is_under_collision_attack(&self, threshold_probe: usize) -> bool {
+        self.load_factor() < MAX_LOAD_FACTOR && self.max_displacement() + 1 > threshold_probe
+    }
+    pub fn freeze_capacity(&mut self) -> CapacityGuard<'_, Self> {
+        CapacityGuard { map: self }
+    }
+}
+impl<K, V> CapacityGuard<'_, OpenHashMap<K, V>>
+where
+    K: Hash + Eq + Clone,
+    V: Eq + Clone + Copy,
+{
+    // Updates always succeed; a new key is refused once it would trigger a resize
+    pub fn insert(&mut self, key: K, value: V) -> Result<InsertOutcome<V>, TableFull<K, V>> {
+        if let Some(index) = self.map.find_index(&key)
+            && let Slot::Some((_, existing)) = &mut self.map.array[index]
+        {
+            return Ok(InsertOutcome::Updated(std::mem::replace(existing, value)));
+        }
+        if self.map.len >= self.map.resize_threshold() {
+            return Err(TableFull { key, value });
+        }
+        self.map.insert(key, value);
+        Ok(InsertOutcome::Inserted)
+    }
+    pub fn delete(&mut self, key: K) -> Slot<K, V> {
+        self.map.delete(key)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,27 @@
+#[derive(Debug, Clone, Hash)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    pub(crate) fn new(capacity: usize) -> Self {
+        BloomFilter {
+            bits: vec![0; capacity.div_ceil(64).max(1)],
+        }
+    }
+    // Two bit positions taken from the low and high halves of the hash
+    fn positions(&self, hash: u64) -> [usize; 2] {
+        let len = self.bits.len() * 64;
+        [hash as usize % len, hash.rotate_left(32) as usize % len]
+    }
+    pub(crate) fn insert(&mut self, hash: u64) {
+        for position in self.positions(hash) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+    pub(crate) fn may_contain(&self, hash: u64) -> bool {
+        self.positions(hash)
+            .iter()
+            .all(|&position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
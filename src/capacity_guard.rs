@@ -0,0 +1,23 @@
+use std::ops::Deref;
+
+// Exclusive borrow of a map at a fixed capacity: reads go through Deref, and the guard's own insert
+// refuses a new key instead of resizing. Dropping the guard hands back the map with normal growth
+#[derive(Debug)]
+pub struct CapacityGuard<'a, M> {
+    pub(crate) map: &'a mut M,
+}
+
+impl<M> Deref for CapacityGuard<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        self.map
+    }
+}
+
+// Returned by CapacityGuard::insert when the key is new and the table is at its resize threshold
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TableFull<K, V> {
+    pub key: K,
+    pub value: V,
+}
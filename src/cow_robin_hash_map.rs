@@ -0,0 +1,96 @@
+use crate::robin_hood_hash_map::{Bucket, RobinHashMap};
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::Arc;
+
+// Cloning shares the backing map; the first mutation through a shared copy clones it
+#[derive(Debug, Clone)]
+pub struct CowRobinHashMap<K, V> {
+    inner: Arc<RobinHashMap<K, V>>,
+}
+
+impl<K, V> CowRobinHashMap<K, V>
+where
+    K: Eq + Clone + Hash,
+    V: Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        CowRobinHashMap {
+            inner: Arc::new(RobinHashMap::new(capacity)),
+        }
+    }
+
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn shares_storage_with(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub fn make_mut(&mut self) -> &mut RobinHashMap<K, V> {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<Bucket<K, V>> {
+        self.make_mut().insert(key, value)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        if !self.inner.contains(key) {
+            return None;
+        }
+        self.make_mut().delete(key)
+    }
+
+    pub fn into_inner(self) -> RobinHashMap<K, V> {
+        Arc::unwrap_or_clone(self.inner)
+    }
+}
+
+impl<K, V> From<RobinHashMap<K, V>> for CowRobinHashMap<K, V> {
+    fn from(map: RobinHashMap<K, V>) -> Self {
+        CowRobinHashMap {
+            inner: Arc::new(map),
+        }
+    }
+}
+
+impl<K, V> Deref for CowRobinHashMap<K, V> {
+    type Target = RobinHashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_is_unchanged_by_mutating_the_original() {
+        let mut map = CowRobinHashMap::new(16);
+        map.insert(1, "one");
+        map.insert(2, "two");
+        let snapshot = map.snapshot();
+        assert!(map.shares_storage_with(&snapshot));
+
+        map.insert(3, "three");
+        assert!(!map.shares_storage_with(&snapshot));
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot.get(&3), None);
+        assert_eq!(map.get(&3), Some(&"three"));
+    }
+
+    #[test]
+    fn deleting_an_absent_key_keeps_sharing() {
+        let mut map = CowRobinHashMap::from(RobinHashMap::new(16));
+        map.insert(1, 1);
+        let snapshot = map.snapshot();
+        assert_eq!(map.delete(&9), None);
+        assert!(map.shares_storage_with(&snapshot));
+        assert_eq!(map.delete(&1), Some(1));
+        assert_eq!(snapshot.into_inner().get(&1), Some(&1));
+    }
+}
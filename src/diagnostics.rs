@@ -0,0 +1,21 @@
+use crate::lifetime_stats::LifetimeStats;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    pub len: usize,
+    pub capacity: usize,
+    pub load_factor: f64,
+    // Always zero for RobinHashMap, which deletes by backward shift
+    pub tombstones: usize,
+    // Entry counts indexed by displacement from the home slot (probe length - 1)
+    pub displacement_histogram: Vec<usize>,
+    pub stats: LifetimeStats,
+    pub home_hit_ratio: f64,
+    pub violations: Vec<String>,
+}
+
+impl Diagnostics {
+    pub fn is_consistent(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
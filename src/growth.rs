@@ -0,0 +1,36 @@
+// Growth policy shared by both maps: inserting a new key once len has reached the threshold grows
+// the table to the larger of growth_factor times the capacity and the size that keeps it under max_load
+
+pub(crate) const GROWTH_FACTOR: f64 = 2.0;
+
+pub(crate) fn threshold(capacity: usize, max_load: f64) -> usize {
+    (capacity as f64 * max_load) as usize
+}
+
+pub(crate) fn grown_capacity(
+    capacity: usize,
+    len: usize,
+    growth_factor: f64,
+    max_load: f64,
+) -> usize {
+    let needed = ((len + 1) as f64 / max_load) as usize + 1;
+    ((capacity as f64 * growth_factor) as usize).max(needed)
+}
+
+// Replays the policy above for `inserts` new keys; the maps pass their own growth factor and load limit
+pub(crate) fn resizes_for(
+    initial_capacity: usize,
+    inserts: usize,
+    growth_factor: f64,
+    max_load: f64,
+) -> usize {
+    let mut capacity = initial_capacity;
+    let mut resizes = 0;
+    for len in 0..inserts {
+        if len >= threshold(capacity, max_load) {
+            capacity = grown_capacity(capacity, len, growth_factor, max_load);
+            resizes += 1;
+        }
+    }
+    resizes
+}
@@ -0,0 +1,13 @@
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Handle<K> {
+    pub(crate) key: K,
+    pub(crate) hash: u64,
+    pub(crate) index: usize,
+    pub(crate) generation: u64,
+}
+
+impl<K> Handle<K> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+}
@@ -0,0 +1,5 @@
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InsertOutcome<V> {
+    Inserted,
+    Updated(V),
+}
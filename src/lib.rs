@@ -1,5 +1,31 @@
 pub mod basic_hash_map;
+mod bloom;
+pub mod capacity_guard;
+pub mod cow_robin_hash_map;
+pub mod diagnostics;
+mod growth;
+pub mod handle;
+pub mod insert_outcome;
+pub mod lifetime_stats;
+pub mod map_delta;
+mod numeric;
+pub mod perfect_map;
+mod rng;
 pub mod robin_hood_hash_map;
+pub mod slot_view;
+pub mod ttl_robin_hash_map;
+pub mod visit_action;
 
 pub use basic_hash_map::{OpenHashMap, Slot};
+pub use capacity_guard::{CapacityGuard, TableFull};
+pub use cow_robin_hash_map::CowRobinHashMap;
+pub use diagnostics::Diagnostics;
+pub use handle::Handle;
+pub use insert_outcome::InsertOutcome;
+pub use lifetime_stats::LifetimeStats;
+pub use map_delta::MapDelta;
+pub use perfect_map::PerfectMap;
 pub use robin_hood_hash_map::{Bucket, RobinHashMap};
+pub use slot_view::SlotView;
+pub use ttl_robin_hash_map::TtlRobinHashMap;
+pub use visit_action::VisitAction;
@@ -0,0 +1,115 @@
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LifetimeStats {
+    // Keys newly added by insert, increment or get_or_compute; overwriting an existing key is not counted
+    pub insert_count: usize,
+    // Calls to the get-style reads (get, get_or, get_cloned, find), hits and misses alike
+    pub get_count: usize,
+    // Calls to delete, whether or not the key was present
+    pub delete_count: usize,
+    // Rebuilds that changed the capacity
+    pub resize_count: usize,
+    // Slots examined by caller-facing inserts, lookups and deletes; rehashing during a rebuild is excluded
+    pub total_probes: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    insert_count: AtomicUsize,
+    get_count: AtomicUsize,
+    delete_count: AtomicUsize,
+    resize_count: AtomicUsize,
+    total_probes: AtomicUsize,
+}
+
+// Atomics rather than Cell so lookups can count through &self without making the map !Sync
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder(Option<Counters>);
+
+impl StatsRecorder {
+    pub(crate) fn enabled() -> Self {
+        StatsRecorder(Some(Counters::default()))
+    }
+    pub(crate) fn record_insert(&self) {
+        if let Some(counters) = &self.0 {
+            counters.insert_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_get(&self) {
+        if let Some(counters) = &self.0 {
+            counters.get_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_delete(&self) {
+        if let Some(counters) = &self.0 {
+            counters.delete_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_resize(&self) {
+        if let Some(counters) = &self.0 {
+            counters.resize_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_probes(&self, probes: usize) {
+        if let Some(counters) = &self.0 {
+            counters.total_probes.fetch_add(probes, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn record_read(&self, reads: &ReadCounter) {
+        if self.0.is_some() {
+            reads.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub(crate) fn snapshot(&self) -> LifetimeStats {
+        match &self.0 {
+            Some(counters) => LifetimeStats {
+                insert_count: counters.insert_count.load(Ordering::Relaxed),
+                get_count: counters.get_count.load(Ordering::Relaxed),
+                delete_count: counters.delete_count.load(Ordering::Relaxed),
+                resize_count: counters.resize_count.load(Ordering::Relaxed),
+                total_probes: counters.total_probes.load(Ordering::Relaxed),
+            },
+            None => LifetimeStats::default(),
+        }
+    }
+}
+
+impl Clone for StatsRecorder {
+    fn clone(&self) -> Self {
+        let stats = self.snapshot();
+        StatsRecorder(self.0.as_ref().map(|_| Counters {
+            insert_count: AtomicUsize::new(stats.insert_count),
+            get_count: AtomicUsize::new(stats.get_count),
+            delete_count: AtomicUsize::new(stats.delete_count),
+            resize_count: AtomicUsize::new(stats.resize_count),
+            total_probes: AtomicUsize::new(stats.total_probes),
+        }))
+    }
+}
+
+// Counters describe how a map was used, not what it holds
+impl Hash for StatsRecorder {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+// Successful reads of one entry, stored in its bucket so the count moves with the entry
+#[derive(Debug, Default)]
+pub(crate) struct ReadCounter(AtomicUsize);
+
+impl ReadCounter {
+    pub(crate) fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for ReadCounter {
+    fn clone(&self) -> Self {
+        ReadCounter(AtomicUsize::new(self.get()))
+    }
+}
+
+impl Hash for ReadCounter {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
@@ -0,0 +1,22 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDelta<K, V> {
+    pub added: Vec<(K, V)>,
+    pub changed: Vec<(K, V)>,
+    pub removed: Vec<K>,
+}
+
+impl<K, V> Default for MapDelta<K, V> {
+    fn default() -> Self {
+        MapDelta {
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> MapDelta<K, V> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
@@ -0,0 +1,15 @@
+// Distance between two integer keys without overflow: i32::MIN to i32::MAX does not fit in i32,
+// but every primitive integer difference fits in u128 once both sides are widened
+pub(crate) fn integer_distance<K>(a: K, b: K) -> u128
+where
+    K: Copy + TryInto<i128> + TryInto<u128>,
+{
+    if let (Ok(a), Ok(b)) = (TryInto::<i128>::try_into(a), TryInto::<i128>::try_into(b)) {
+        return a.abs_diff(b);
+    }
+    // Only u128 values above i128::MAX get here, and those always widen to u128
+    match (TryInto::<u128>::try_into(a), TryInto::<u128>::try_into(b)) {
+        (Ok(a), Ok(b)) => a.abs_diff(b),
+        _ => u128::MAX,
+    }
+}
@@ -0,0 +1,223 @@
+use crate::rng::mix;
+use fxhash::FxHasher64;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+// Keys per displacement bucket; larger buckets build faster but need more seed attempts each
+const KEYS_PER_BUCKET: usize = 4;
+const MAX_SEED: u32 = 1 << 24;
+// Seed stored for a bucket whose keys no seed below the limit could place; they live in overflow
+const NO_SEED: u32 = u32::MAX;
+
+fn hash_with_fxhash<T: Hash>(t: &T) -> u64 {
+    let mut s = FxHasher64::default();
+    t.hash(&mut s);
+    s.finish()
+}
+
+fn seeded_slot(hash: u64, seed: u32, len: usize) -> usize {
+    (mix(hash ^ (seed as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15)) % len as u64) as usize
+}
+
+// Immutable map built with hash-and-displace: each bucket of keys gets a seed that sends
+// every key to its own slot, so a lookup reads exactly one slot. No seed can separate keys
+// whose full 64-bit hashes are equal, so all but the first of those go to a small overflow list,
+// as do the keys of any bucket the seed search gives up on
+#[derive(Debug, Clone)]
+pub struct PerfectMap<K, V> {
+    seeds: Vec<u32>,
+    entries: Vec<Option<(K, V)>>,
+    overflow: Vec<(K, V)>,
+    len: usize,
+}
+
+impl<K, V> PerfectMap<K, V>
+where
+    K: Eq + Hash,
+{
+    pub(crate) fn build(pairs: Vec<(K, V)>) -> Self {
+        Self::build_with_seed_limit(pairs, MAX_SEED)
+    }
+
+    fn build_with_seed_limit(pairs: Vec<(K, V)>, seed_limit: u32) -> Self {
+        let total = pairs.len();
+        let mut seen = HashSet::new();
+        let mut hashes = Vec::with_capacity(pairs.len());
+        let mut unique = Vec::with_capacity(pairs.len());
+        let mut overflow = Vec::new();
+        for pair in pairs {
+            let hash = hash_with_fxhash(&pair.0);
+            if seen.insert(hash) {
+                hashes.push(hash);
+                unique.push(pair);
+            } else {
+                overflow.push(pair);
+            }
+        }
+        let pairs = unique;
+        let len = pairs.len();
+        let bucket_count = len.div_ceil(KEYS_PER_BUCKET).max(1);
+        let mut buckets = vec![Vec::new(); bucket_count];
+        for (position, &hash) in hashes.iter().enumerate() {
+            buckets[(mix(hash) % bucket_count as u64) as usize].push(position);
+        }
+        // Place the largest buckets first while the table still has room
+        let mut order: Vec<usize> = (0..bucket_count).collect();
+        order.sort_by_key(|&bucket| std::cmp::Reverse(buckets[bucket].len()));
+
+        let mut seeds = vec![0u32; bucket_count];
+        let mut taken = vec![false; len];
+        let mut slots = Vec::new();
+        for bucket in order {
+            if buckets[bucket].is_empty() {
+                break;
+            }
+            let found = (0..seed_limit).find(|&seed| {
+                slots.clear();
+                for &position in &buckets[bucket] {
+                    let slot = seeded_slot(hashes[position], seed, len);
+                    if taken[slot] || slots.contains(&slot) {
+                        return false;
+                    }
+                    slots.push(slot);
+                }
+                true
+            });
+            let Some(seed) = found else {
+                seeds[bucket] = NO_SEED;
+                continue;
+            };
+            for &slot in &slots {
+                taken[slot] = true;
+            }
+            seeds[bucket] = seed;
+        }
+
+        let mut table: Vec<Option<(K, V)>> = (0..len).map(|_| None).collect();
+        for (position, pair) in pairs.into_iter().enumerate() {
+            let hash = hashes[position];
+            let seed = seeds[(mix(hash) % bucket_count as u64) as usize];
+            if seed == NO_SEED {
+                overflow.push(pair);
+            } else {
+                table[seeded_slot(hash, seed, len)] = Some(pair);
+            }
+        }
+        PerfectMap {
+            seeds,
+            entries: table,
+            overflow,
+            len: total,
+        }
+    }
+
+    // Returns the entry for `key`, if any, and the number of entries compared
+    fn find(&self, key: &K) -> (Option<&(K, V)>, usize) {
+        if self.len == 0 {
+            return (None, 0);
+        }
+        let hash = hash_with_fxhash(key);
+        let seed = self.seeds[(mix(hash) % self.seeds.len() as u64) as usize];
+        if seed != NO_SEED
+            && let Some(entry) = &self.entries[seeded_slot(hash, seed, self.entries.len())]
+            && entry.0 == *key
+        {
+            return (Some(entry), 1);
+        }
+        match self.overflow.iter().position(|(other, _)| other == key) {
+            Some(position) => (Some(&self.overflow[position]), position + 2),
+            None => (None, self.overflow.len() + 1),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find(key).0.map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.find(key).0.is_some()
+    }
+
+    // Entries compared to look up `key`: always 1 unless the key spilled into overflow
+    pub fn probe_count(&self, key: &K) -> usize {
+        self.find(key).1
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries
+            .iter()
+            .flatten()
+            .chain(&self.overflow)
+            .map(|(key, value)| (key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every key hashes identically, so only the overflow list can tell them apart
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Colliding(u32);
+
+    impl Hash for Colliding {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            0u8.hash(state);
+        }
+    }
+
+    #[test]
+    fn every_key_resolves_in_one_probe() {
+        let map = PerfectMap::build((0..1000u32).map(|key| (key, key * 3)).collect());
+        assert_eq!(map.len(), 1000);
+        for key in 0..1000u32 {
+            assert_eq!(map.probe_count(&key), 1);
+            assert_eq!(map.get(&key), Some(&(key * 3)));
+        }
+        assert!(!map.contains(&1000));
+    }
+
+    #[test]
+    fn keys_with_equal_hashes_are_still_found() {
+        let map = PerfectMap::build((0..4).map(|id| (Colliding(id), id)).collect());
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.iter().count(), 4);
+        for id in 0..4 {
+            assert_eq!(map.get(&Colliding(id)), Some(&id));
+        }
+        assert_eq!(map.get(&Colliding(9)), None);
+    }
+
+    #[test]
+    fn buckets_without_a_seed_spill_into_overflow() {
+        // A one-seed search fails for most buckets; their keys must still be found
+        let map =
+            PerfectMap::build_with_seed_limit((0..200u32).map(|key| (key, key + 1)).collect(), 1);
+        assert!(!map.overflow.is_empty());
+        assert_eq!(map.len(), 200);
+        assert_eq!(map.iter().count(), 200);
+        for key in 0..200u32 {
+            assert_eq!(map.get(&key), Some(&(key + 1)));
+        }
+        assert_eq!(map.get(&200), None);
+
+        let map = PerfectMap::build_with_seed_limit((0..10u32).map(|key| (key, key)).collect(), 0);
+        assert_eq!(map.overflow.len(), 10);
+        assert!(map.contains(&7));
+    }
+
+    #[test]
+    fn empty_map_finds_nothing() {
+        let map: PerfectMap<u32, u32> = PerfectMap::build(Vec::new());
+        assert!(map.is_empty());
+        assert_eq!(map.get(&1), None);
+    }
+}
@@ -1,5 +1,27 @@
+use crate::bloom::BloomFilter;
+use crate::capacity_guard::{CapacityGuard, TableFull};
+use crate::diagnostics::Diagnostics;
+use crate::growth::{self, GROWTH_FACTOR};
+use crate::handle::Handle;
+use crate::insert_outcome::InsertOutcome;
+use crate::lifetime_stats::{LifetimeStats, ReadCounter, StatsRecorder};
+use crate::map_delta::MapDelta;
+use crate::numeric;
+use crate::perfect_map::PerfectMap;
+use crate::rng::{SplitMix64, mix};
+use crate::slot_view::SlotView;
+use crate::visit_action::VisitAction;
 use fxhash::FxHasher64;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+const MAX_LOAD_FACTOR: f64 = 0.9;
+const CACHE_LINE_BYTES: usize = 64;
+const MAX_PROBE_DOUBLINGS: usize = 8;
+fn capacity_for(len: usize) -> usize {
+    (len as f64 / MAX_LOAD_FACTOR) as usize + 1
+}
 fn hash_with_fxhash<T: Hash>(t: &T) -> u64 {
     let mut s = FxHasher64::default();
     t.hash(&mut s);
@@ -11,17 +33,25 @@ pub struct Bucket<K, V> {
     key: K,
     value: V,
     probe_length: usize,
+    reads: ReadCounter,
 }
 #[derive(Debug, Clone, Hash)]
 pub struct RobinHashMap<K, V> {
     array: Vec<Option<Bucket<K, V>>>,
     max_psl: usize,
     capacity: usize,
+    len: usize,
+    salt: u64,
+    stats: StatsRecorder,
+    bloom: Option<BloomFilter>,
+    generation: u64,
+    last_resize_moved: usize,
+    peak_len: usize,
 }
 impl<K, V> RobinHashMap<K, V>
 where
     K: Eq + Clone + Hash,
-    V: Eq + Clone,
+    V: Clone,
 {
     pub fn new(capacity: usize) -> Self {
         let array = vec![None; capacity];
@@ -30,97 +60,266 @@ where
             array,
             max_psl,
             capacity,
+            len: 0,
+            salt: 0,
+            stats: StatsRecorder::default(),
+            bloom: None,
+            generation: 0,
+            last_resize_moved: 0,
+            peak_len: 0,
+        }
+    }
+
+    pub fn with_stats(mut self) -> Self {
+        self.stats = StatsRecorder::enabled();
+        self
+    }
+
+    pub fn lifetime_stats(&self) -> LifetimeStats {
+        self.stats.snapshot()
+    }
+
+    pub fn with_bloom(mut self) -> Self {
+        let mut bloom = BloomFilter::new(self.capacity);
+        for bucket in self.array.iter().flatten() {
+            bloom.insert(hash_with_fxhash(&bucket.key));
         }
+        self.bloom = Some(bloom);
+        self
     }
     pub fn insert(&mut self, key: K, value: V) -> Option<Bucket<K, V>> {
-        let mut index = (hash_with_fxhash(&key) as usize) % self.capacity;
+        self.insert_hashed(hash_with_fxhash(&key), key, value)
+    }
+
+    fn insert_hashed(&mut self, hash: u64, key: K, value: V) -> Option<Bucket<K, V>> {
+        if self.len >= self.resize_threshold()
+            && (self.capacity == 0 || self.probe(hash, &key).0.is_none())
+        {
+            self.grow();
+        }
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(hash);
+        }
+        let mut index = self.home_slot(hash);
         let mut incoming = Bucket {
             key,
             value,
             probe_length: 1,
+            reads: ReadCounter::default(),
         };
+        let mut probes = 1;
         loop {
+            if probes > self.capacity {
+                // Wrapped the whole table without a free slot: grow, then place the bucket still in hand
+                self.stats.record_probes(probes - 1);
+                self.stats.record_insert();
+                self.rebuild((self.capacity * 2).max(1));
+                self.insert_bucket(incoming);
+                return None;
+            }
             match &mut self.array[index] {
                 None => {
                     self.max_psl = self.max_psl.max(incoming.probe_length);
                     self.array[index] = Some(incoming);
+                    self.len += 1;
+                    self.peak_len = self.peak_len.max(self.len);
+                    self.stats.record_probes(probes);
+                    self.stats.record_insert();
                     return None;
                 }
                 Some(bucket) if bucket.key == incoming.key => {
                     std::mem::swap(&mut bucket.value, &mut incoming.value);
+                    self.stats.record_probes(probes);
                     return Some(incoming); // return old value wrapped in bucket
                 }
                 Some(bucket) if bucket.probe_length < incoming.probe_length => {
+                    self.max_psl = self.max_psl.max(incoming.probe_length);
                     std::mem::swap(bucket, &mut incoming); // swap entire bucket
                 }
                 _ => {}
             }
             index = (index + 1) % self.capacity;
             incoming.probe_length += 1;
+            probes += 1;
         }
     }
 
-    pub fn contains(&self, key: &K) -> bool {
-        let mut index = (hash_with_fxhash(key) as usize) % self.capacity;
-        let mut psl = 1;
+    fn insert_unique(&mut self, key: K, value: V) {
+        self.insert_bucket(Bucket {
+            key,
+            value,
+            probe_length: 1,
+            reads: ReadCounter::default(),
+        });
+    }
 
-        while psl <= self.max_psl {
-            match &self.array[index] {
-                None => return false,
-                Some(bucket) if bucket.key == *key => return true,
-                Some(bucket) if bucket.probe_length < psl => return false,
+    // Places an existing bucket afresh, keeping its read count
+    fn insert_bucket(&mut self, mut bucket: Bucket<K, V>) {
+        let hash = hash_with_fxhash(&bucket.key);
+        if let Some(bloom) = &mut self.bloom {
+            bloom.insert(hash);
+        }
+        bucket.probe_length = 1;
+        let index = self.home_slot(hash);
+        self.carry(bucket, index);
+    }
+
+    // Walks from `index` until `incoming` lands in an empty slot, swapping it with any richer bucket on the way
+    fn carry(&mut self, mut incoming: Bucket<K, V>, mut index: usize) {
+        let mut probes = 1;
+        loop {
+            if probes > self.capacity {
+                // Wrapped the whole table without a free slot: grow, then place the bucket still in hand
+                self.stats.record_probes(probes - 1);
+                self.rebuild((self.capacity * 2).max(1));
+                self.insert_bucket(incoming);
+                return;
+            }
+            match &mut self.array[index] {
+                None => {
+                    self.max_psl = self.max_psl.max(incoming.probe_length);
+                    self.array[index] = Some(incoming);
+                    self.len += 1;
+                    self.peak_len = self.peak_len.max(self.len);
+                    self.stats.record_probes(probes);
+                    return;
+                }
+                Some(bucket) if bucket.probe_length < incoming.probe_length => {
+                    self.max_psl = self.max_psl.max(incoming.probe_length);
+                    std::mem::swap(bucket, &mut incoming);
+                }
                 _ => {}
             }
             index = (index + 1) % self.capacity;
-            psl += 1;
+            incoming.probe_length += 1;
+            probes += 1;
         }
-        false
     }
 
-    pub fn get(&self, key: &K) -> Option<&V> {
-        let mut index = (hash_with_fxhash(key) as usize) % self.capacity;
-        let mut psl = 1;
-
-        while psl <= self.max_psl {
-            match &self.array[index] {
-                None => return None,
-                Some(bucket) if bucket.key == *key => return Some(&bucket.value),
-                Some(bucket) if bucket.probe_length < psl => return None,
-                _ => {}
+    // One walk: returns the slot holding `key`, or places make()'s value where the key belongs
+    fn find_or_insert_with<F: FnOnce() -> V>(&mut self, hash: u64, key: K, make: F) -> usize {
+        if self.capacity > 0 {
+            let mut index = self.home_slot(hash);
+            let mut psl = 1;
+            while psl <= self.capacity {
+                match &self.array[index] {
+                    Some(bucket) if bucket.key == key => {
+                        self.stats.record_probes(psl);
+                        return index;
+                    }
+                    Some(bucket) if bucket.probe_length >= psl => {}
+                    // An empty slot or a richer bucket: the key is absent and belongs here
+                    _ => break,
+                }
+                index = (index + 1) % self.capacity;
+                psl += 1;
+            }
+            self.stats.record_probes(psl.min(self.capacity));
+            if psl <= self.capacity && self.len < self.resize_threshold() {
+                self.stats.record_insert();
+                if let Some(bloom) = &mut self.bloom {
+                    bloom.insert(hash);
+                }
+                self.max_psl = self.max_psl.max(psl);
+                let incoming = Bucket {
+                    key,
+                    value: make(),
+                    probe_length: psl,
+                    reads: ReadCounter::default(),
+                };
+                // Staying under the load threshold leaves an empty slot for the displaced bucket
+                match self.array[index].replace(incoming) {
+                    Some(mut displaced) => {
+                        displaced.probe_length += 1;
+                        self.carry(displaced, (index + 1) % self.capacity);
+                    }
+                    None => {
+                        self.len += 1;
+                        self.peak_len = self.peak_len.max(self.len);
+                    }
+                }
+                return index;
             }
-            index = (index + 1) % self.capacity;
-            psl += 1;
         }
-        None
+        self.grow();
+        self.find_or_insert_with(hash, key, make)
     }
 
-    pub fn delete(&mut self, key: &K) -> Option<V> {
-        let mut index = (hash_with_fxhash(key) as usize) % self.capacity;
+    fn home_slot(&self, hash: u64) -> usize {
+        self.home_slot_in(hash, self.capacity)
+    }
+
+    // The salt goes in before the mix, so a new salt also separates keys that only shared a home slot
+    fn home_slot_in(&self, hash: u64, capacity: usize) -> usize {
+        (mix(hash ^ self.salt) % capacity as u64) as usize
+    }
+
+    fn find_index(&self, key: &K) -> Option<usize> {
+        self.find_index_hashed(hash_with_fxhash(key), key)
+    }
+
+    fn find_index_hashed(&self, hash: u64, key: &K) -> Option<usize> {
+        let (index, probes) = self.probe(hash, key);
+        self.stats.record_probes(probes);
+        index
+    }
+
+    // Returns the slot holding `key`, if any, and the number of slots examined
+    fn probe(&self, hash: u64, key: &K) -> (Option<usize>, usize) {
+        if let Some(bloom) = &self.bloom
+            && !bloom.may_contain(hash)
+        {
+            return (None, 0);
+        }
+        let mut index = self.home_slot(hash);
         let mut psl = 1;
 
-        // Find the key
-        while psl <= self.max_psl {
+        // A max_psl beyond the capacity would only revisit slots, so stop after one lap
+        while psl <= self.max_psl.min(self.capacity) {
             match &self.array[index] {
-                None => return None,
-                Some(bucket) if bucket.key == *key => break,
-                Some(bucket) if bucket.probe_length < psl => return None,
+                None => return (None, psl),
+                Some(bucket) if bucket.key == *key => return (Some(index), psl),
+                Some(bucket) if bucket.probe_length < psl => return (None, psl),
                 _ => {}
             }
             index = (index + 1) % self.capacity;
             psl += 1;
         }
+        (None, psl - 1)
+    }
 
-        if psl > self.max_psl {
-            return None;
-        }
+    pub fn contains(&self, key: &K) -> bool {
+        self.find_index(key).is_some()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.stats.record_get();
+        let index = self.find_index(key)?;
+        let bucket = self.array[index].as_ref()?;
+        self.stats.record_read(&bucket.reads);
+        Some(&bucket.value)
+    }
 
-        // Remove the element and get its value
+    fn lookup(&self, key: &K) -> Option<&V> {
+        let index = self.find_index(key)?;
+        self.array[index].as_ref().map(|bucket| &bucket.value)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        self.stats.record_delete();
+        let index = self.find_index(key)?;
+        Some(self.remove_at(index).value)
+    }
+
+    fn remove_at(&mut self, index: usize) -> Bucket<K, V> {
+        // Remove the element
         let removed = self.array[index].take().unwrap();
-        let removed_value = removed.value;
+        self.len -= 1;
 
         // Backward shift: move elements back to fill the gap
         let mut empty_index = index;
-        loop {
+        // Bounded so a corrupted table with no free or home-slot bucket cannot spin forever
+        for _ in 1..self.capacity {
             let next_index = (empty_index + 1) % self.capacity;
 
             match &self.array[next_index] {
@@ -137,6 +336,1267 @@ where
             }
         }
 
-        Some(removed_value)
+        removed
+    }
+
+    pub fn apply_updates(&mut self, updates: &Self) {
+        for bucket in updates.array.iter().flatten() {
+            if let Some(index) = self.find_index(&bucket.key)
+                && let Some(existing) = &mut self.array[index]
+            {
+                existing.value = bucket.value.clone();
+            }
+        }
+    }
+
+    pub fn best_case_lookup(&self) -> Option<(&K, usize)> {
+        self.array
+            .iter()
+            .flatten()
+            .map(|bucket| (&bucket.key, bucket.probe_length - 1))
+            .min_by_key(|&(_, distance)| distance)
+    }
+
+    pub fn shard(self, shards: usize) -> Vec<Self> {
+        assert!(shards > 0, "cannot shard a map into zero shards");
+        let mut maps: Vec<Self> = (0..shards)
+            .map(|_| Self::new(capacity_for(self.len / shards)))
+            .collect();
+        for bucket in self.array.into_iter().flatten() {
+            let target = (hash_with_fxhash(&bucket.key) as usize) % shards;
+            maps[target].reserve(1);
+            maps[target].insert(bucket.key, bucket.value);
+        }
+        maps
+    }
+
+    pub fn get_or<'a>(&'a self, key: &K, default: &'a V) -> &'a V {
+        self.get(key).unwrap_or(default)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity as f64
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        let load = self.load_factor();
+        if load >= MAX_LOAD_FACTOR {
+            return false;
+        }
+        // Expected PSL grows with 1 / (1 - load), the maximum roughly with log2(capacity)
+        let psl_bound = (self.capacity as f64).log2().ceil() + 1.0 / (1.0 - load);
+        self.max_psl as f64 <= psl_bound
+    }
+
+    pub fn move_entry_to(&mut self, key: &K, dest: &mut Self) -> bool {
+        match self.find_index(key) {
+            Some(index) => {
+                let bucket = self.remove_at(index);
+                dest.insert(bucket.key, bucket.value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.array
+            .iter()
+            .flatten()
+            .map(|bucket| (&bucket.key, &bucket.value))
+    }
+
+    pub fn count_values<F: FnMut(&V) -> bool>(&self, mut pred: F) -> usize {
+        self.entries().filter(|(_, value)| pred(value)).count()
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = self.find_index(key)?;
+        self.array[index].as_mut().map(|bucket| &mut bucket.value)
+    }
+
+    pub fn get_mut_or_panic(&mut self, key: &K) -> &mut V
+    where
+        K: std::fmt::Debug,
+    {
+        match self.get_mut(key) {
+            Some(value) => value,
+            None => panic!("key {:?} not found in RobinHashMap", key),
+        }
+    }
+
+    pub fn fill_histogram(&self, buckets: usize) -> Vec<usize> {
+        let mut histogram = vec![0; buckets];
+        if buckets == 0 {
+            return histogram;
+        }
+        for (index, slot) in self.array.iter().enumerate() {
+            if slot.is_some() {
+                histogram[index * buckets / self.capacity] += 1;
+            }
+        }
+        histogram
+    }
+
+    pub fn take(&mut self, key: &K) -> Option<V>
+    where
+        V: Default,
+    {
+        self.get_mut(key).map(std::mem::take)
+    }
+
+    pub fn prehash(keys: &[K]) -> Vec<u64> {
+        keys.iter().map(hash_with_fxhash).collect()
+    }
+
+    pub fn get_prehashed(&self, hash: u64, key: &K) -> Option<&V> {
+        let index = self.find_index_hashed(hash, key)?;
+        self.array[index].as_ref().map(|bucket| &bucket.value)
+    }
+
+    pub fn dedup(&mut self) -> usize {
+        let old = std::mem::replace(&mut self.array, vec![None; self.capacity]);
+        self.len = 0;
+        self.max_psl = 0;
+        let mut removed = 0;
+        for bucket in old.into_iter().flatten() {
+            if self.contains(&bucket.key) {
+                removed += 1;
+            } else {
+                self.insert(bucket.key, bucket.value);
+            }
+        }
+        removed
+    }
+
+    pub fn total_probe_cost(&self) -> usize {
+        self.array
+            .iter()
+            .flatten()
+            .map(|bucket| bucket.probe_length)
+            .sum()
+    }
+
+    fn rebuild(&mut self, capacity: usize) {
+        if capacity != self.capacity {
+            self.stats.record_resize();
+            self.last_resize_moved = self.len;
+        }
+        let old = std::mem::replace(&mut self.array, vec![None; capacity]);
+        // Rehashing is bookkeeping, not caller work, so its probes stay out of the stats
+        let stats = std::mem::take(&mut self.stats);
+        self.capacity = capacity;
+        self.len = 0;
+        self.max_psl = 0;
+        self.generation += 1;
+        if self.bloom.is_some() {
+            self.bloom = Some(BloomFilter::new(capacity));
+        }
+        for bucket in old.into_iter().flatten() {
+            self.insert_bucket(bucket);
+        }
+        self.stats = stats;
+    }
+
+    pub fn reseed(&mut self, salt: u64) {
+        self.salt = salt;
+        self.rebuild(self.capacity);
+    }
+
+    pub fn from_sorted_unique(pairs: &[(K, V)]) -> Self
+    where
+        K: Ord,
+    {
+        debug_assert!(
+            pairs.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "from_sorted_unique requires keys sorted in strictly ascending order"
+        );
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (key, value) in pairs {
+            map.insert_unique(key.clone(), value.clone());
+        }
+        map
+    }
+
+    pub fn filter_keys<F: FnMut(&K) -> bool>(&self, mut pred: F) -> impl Iterator<Item = (&K, &V)> {
+        self.entries().filter(move |(key, _)| pred(key))
+    }
+
+    pub fn get_with_index(&self, key: &K) -> Option<(usize, &V)> {
+        let index = self.find_index(key)?;
+        self.array[index]
+            .as_ref()
+            .map(|bucket| (index, &bucket.value))
+    }
+
+    fn max_displacement(&self) -> usize {
+        self.array
+            .iter()
+            .flatten()
+            .map(|bucket| bucket.probe_length - 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Doubles until no entry is displaced more than `limit` slots, at most MAX_PROBE_DOUBLINGS times.
+    // Growth cannot separate keys with equal hashes, so it also stops once a doubling no longer helps;
+    // the limit may then still be exceeded
+    pub fn enforce_max_probe(&mut self, limit: usize) -> bool {
+        let mut worst = self.max_displacement();
+        let mut doublings = 0;
+        while worst > limit && doublings < MAX_PROBE_DOUBLINGS {
+            self.rebuild(self.capacity * 2);
+            doublings += 1;
+            let shorter = self.max_displacement();
+            if shorter >= worst {
+                break;
+            }
+            worst = shorter;
+        }
+        doublings > 0
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let needed = capacity_for(self.len + additional);
+        if needed > self.capacity {
+            self.rebuild(needed.max(self.capacity * 2));
+        }
+    }
+
+    pub fn delta_since(&self, base: &Self) -> MapDelta<K, V>
+    where
+        V: PartialEq,
+    {
+        let mut delta = MapDelta::default();
+        for (key, value) in self.entries() {
+            match base.lookup(key) {
+                None => delta.added.push((key.clone(), value.clone())),
+                Some(old) if old != value => delta.changed.push((key.clone(), value.clone())),
+                Some(_) => {}
+            }
+        }
+        for (key, _) in base.entries() {
+            if !self.contains(key) {
+                delta.removed.push(key.clone());
+            }
+        }
+        delta
+    }
+
+    pub fn apply_delta(&mut self, delta: MapDelta<K, V>) {
+        for key in &delta.removed {
+            self.delete(key);
+        }
+        self.reserve(delta.added.len());
+        for (key, value) in delta.added.into_iter().chain(delta.changed) {
+            self.insert(key, value);
+        }
+    }
+
+    pub fn get_first(&self, keys: &[K]) -> Option<(&K, &V)> {
+        keys.iter()
+            .find_map(|key| self.find_index(key))
+            .and_then(|index| self.array[index].as_ref())
+            .map(|bucket| (&bucket.key, &bucket.value))
+    }
+
+    // Sized for the distinct keys, so repeats in `keys` do not inflate the table. Slots have nowhere
+    // to cache a hash, so each key is hashed again when its value is inserted
+    pub fn prepare_for(keys: &[K]) -> Self {
+        let distinct: HashSet<&K> = keys.iter().collect();
+        Self::new(capacity_for(distinct.len()))
+    }
+
+    pub fn would_displace(&self, key: &K) -> usize {
+        let mut index = self.home_slot(hash_with_fxhash(key));
+        let mut probe_length = 1;
+        let mut displaced = 0;
+        // Mirrors insert: after each swap we carry the displaced bucket onwards
+        for _ in 0..self.capacity {
+            match &self.array[index] {
+                None => return displaced,
+                Some(bucket) if bucket.key == *key => return 0,
+                Some(bucket) if bucket.probe_length < probe_length => {
+                    displaced += 1;
+                    probe_length = bucket.probe_length;
+                }
+                _ => {}
+            }
+            index = (index + 1) % self.capacity;
+            probe_length += 1;
+        }
+        displaced
+    }
+
+    pub fn clone_with_capacity(&self, capacity: usize) -> Self {
+        assert!(
+            capacity >= self.len && capacity > 0,
+            "capacity {} cannot hold {} entries",
+            capacity,
+            self.len
+        );
+        let mut copy = self.clone();
+        copy.rebuild(capacity);
+        copy
+    }
+
+    pub fn raw_iter(&self) -> impl Iterator<Item = SlotView<'_, K, V>> {
+        self.array.iter().map(|slot| match slot {
+            Some(bucket) => SlotView::Occupied(&bucket.key, &bucket.value, bucket.probe_length - 1),
+            None => SlotView::Empty,
+        })
+    }
+
+    pub fn swap_values(&mut self, a: &K, b: &K) -> bool {
+        let (Some(i), Some(j)) = (self.find_index(a), self.find_index(b)) else {
+            return false;
+        };
+        if i == j {
+            return false;
+        }
+        let (low, high) = self.array.split_at_mut(i.max(j));
+        match (&mut low[i.min(j)], &mut high[0]) {
+            (Some(first), Some(second)) => {
+                std::mem::swap(&mut first.value, &mut second.value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn top_n(&self, n: usize) -> Vec<(&K, &V)>
+    where
+        V: Ord,
+    {
+        if n == 0 {
+            return Vec::new();
+        }
+        // Min-heap of the n largest seen so far, with the slot index as tie-breaker
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        for (index, slot) in self.array.iter().enumerate() {
+            if let Some(bucket) = slot {
+                heap.push(Reverse((&bucket.value, index)));
+                if heap.len() > n {
+                    heap.pop();
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .filter_map(|Reverse((_, index))| self.array[index].as_ref())
+            .map(|bucket| (&bucket.key, &bucket.value))
+            .collect()
+    }
+
+    pub fn insert_handle(&mut self, key: K, value: V) -> Handle<K> {
+        let hash = hash_with_fxhash(&key);
+        self.reserve(1);
+        self.insert(key.clone(), value);
+        let index = self.find_index_hashed(hash, &key).unwrap();
+        Handle {
+            key,
+            hash,
+            index,
+            generation: self.generation,
+        }
+    }
+
+    pub fn get_by_handle(&self, handle: &Handle<K>) -> Option<&V> {
+        // The cached index is only a hint: Robin Hood swaps and backward shifts move buckets
+        if handle.generation == self.generation
+            && let Some(Some(bucket)) = self.array.get(handle.index)
+            && bucket.key == handle.key
+        {
+            return Some(&bucket.value);
+        }
+        self.get_prehashed(handle.hash, &handle.key)
+    }
+
+    fn home_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.array.iter().enumerate().filter_map(|(index, slot)| {
+            slot.as_ref()
+                .map(|bucket| (index + self.capacity - (bucket.probe_length - 1)) % self.capacity)
+        })
+    }
+
+    pub fn home_slot_entropy(&self) -> f64 {
+        let mut counts = vec![0usize; self.capacity];
+        for home in self.home_indices() {
+            counts[home] += 1;
+        }
+        let total = self.len as f64;
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    pub fn invert(&self) -> RobinHashMap<V, Vec<K>>
+    where
+        V: Hash + Eq,
+    {
+        let mut inverted: RobinHashMap<V, Vec<K>> = RobinHashMap::new(self.capacity);
+        for (key, value) in self.entries() {
+            match inverted.get_mut(value) {
+                Some(keys) => keys.push(key.clone()),
+                None => {
+                    inverted.insert(value.clone(), vec![key.clone()]);
+                }
+            }
+        }
+        inverted
+    }
+
+    pub fn retain_visit<F: FnMut(&K, &V) -> VisitAction>(&mut self, mut f: F) {
+        // Snapshot the keys first: backward shifts would otherwise move unvisited buckets behind us
+        let keys: Vec<K> = self.entries().map(|(key, _)| key.clone()).collect();
+        for key in keys {
+            let Some(index) = self.find_index(&key) else {
+                continue;
+            };
+            let action = match &self.array[index] {
+                Some(bucket) => f(&bucket.key, &bucket.value),
+                None => continue,
+            };
+            match action {
+                VisitAction::Keep => {}
+                VisitAction::Remove => {
+                    self.remove_at(index);
+                }
+                VisitAction::Stop => return,
+            }
+        }
+    }
+
+    pub fn resize_threshold(&self) -> usize {
+        growth::threshold(self.capacity, MAX_LOAD_FACTOR)
+    }
+
+    fn grow(&mut self) {
+        self.rebuild(growth::grown_capacity(
+            self.capacity,
+            self.len,
+            GROWTH_FACTOR,
+            MAX_LOAD_FACTOR,
+        ));
+    }
+
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let live = self.array.iter().flatten().count();
+        if live != self.len {
+            return Err(format!("len is {} but {} buckets are live", self.len, live));
+        }
+        for (index, slot) in self.array.iter().enumerate() {
+            let Some(bucket) = slot else {
+                continue;
+            };
+            if bucket.probe_length == 0 || bucket.probe_length > self.max_psl {
+                return Err(format!(
+                    "slot {} has probe length {} outside 1..={}",
+                    index, bucket.probe_length, self.max_psl
+                ));
+            }
+            let home = self.home_slot(hash_with_fxhash(&bucket.key));
+            if (home + bucket.probe_length - 1) % self.capacity != index {
+                return Err(format!(
+                    "slot {} has probe length {} but its home slot is {}",
+                    index, bucket.probe_length, home
+                ));
+            }
+            // A displaced bucket needs a neighbour at most one step closer to its own home
+            if bucket.probe_length > 1 {
+                let previous = (index + self.capacity - 1) % self.capacity;
+                match &self.array[previous] {
+                    Some(before) if before.probe_length + 1 >= bucket.probe_length => {}
+                    _ => {
+                        return Err(format!(
+                            "slot {} breaks the Robin Hood ordering with slot {}",
+                            index, previous
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn verified_clone(&self) -> Self {
+        let copy = self.clone();
+        if let Err(violation) = copy.check_invariants() {
+            panic!("cloned RobinHashMap is inconsistent: {}", violation);
+        }
+        copy
+    }
+
+    pub fn probe_overhead(&self, key: &K) -> Option<usize> {
+        let index = self.find_index(key)?;
+        self.array[index]
+            .as_ref()
+            .map(|bucket| bucket.probe_length - 1)
+    }
+
+    pub fn clear(&mut self) {
+        self.array.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+        self.max_psl = 0;
+        if self.bloom.is_some() {
+            self.bloom = Some(BloomFilter::new(self.capacity));
+        }
+    }
+
+    pub fn replace_all(&mut self, pairs: impl IntoIterator<Item = (K, V)>) {
+        let pairs: Vec<(K, V)> = pairs.into_iter().collect();
+        self.clear();
+        self.reserve(pairs.len());
+        for (key, value) in pairs {
+            self.insert(key, value);
+        }
+    }
+
+    pub fn last_resize_moved(&self) -> usize {
+        self.last_resize_moved
+    }
+
+    pub fn with_value<R, F: FnOnce(&V) -> R>(&self, key: &K, f: F) -> Option<R> {
+        self.lookup(key).map(f)
+    }
+
+    pub fn weighted_probe_cost(&self, query_counts: &[(K, usize)]) -> f64 {
+        let total: usize = query_counts.iter().map(|(_, count)| count).sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let weighted: usize = query_counts
+            .iter()
+            .map(|(key, count)| self.probe(hash_with_fxhash(key), key).1 * count)
+            .sum();
+        weighted as f64 / total as f64
+    }
+
+    pub fn from_prehashed(pairs: impl IntoIterator<Item = (u64, K, V)>) -> Self {
+        let pairs: Vec<(u64, K, V)> = pairs.into_iter().collect();
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (hash, key, value) in pairs {
+            map.insert_hashed(hash, key, value);
+        }
+        map
+    }
+
+    pub fn occupancy_rle(&self) -> Vec<(bool, usize)> {
+        let mut runs: Vec<(bool, usize)> = Vec::new();
+        for slot in &self.array {
+            let occupied = slot.is_some();
+            match runs.last_mut() {
+                Some((state, length)) if *state == occupied => *length += 1,
+                _ => runs.push((occupied, 1)),
+            }
+        }
+        runs
+    }
+
+    pub fn merge_max<F: FnMut(&V, &V) -> Ordering>(&mut self, other: Self, mut cmp: F) {
+        for bucket in other.array.into_iter().flatten() {
+            match self.get_mut(&bucket.key) {
+                Some(existing) => {
+                    if cmp(&bucket.value, existing) == Ordering::Greater {
+                        *existing = bucket.value;
+                    }
+                }
+                None => {
+                    self.reserve(1);
+                    self.insert(bucket.key, bucket.value);
+                }
+            }
+        }
+    }
+
+    pub fn colliding_keys_with(&self, other: &Self) -> Vec<&K> {
+        let occupied_homes: HashSet<usize> = other.home_indices().collect();
+        self.entries()
+            .map(|(key, _)| key)
+            .filter(|key| occupied_homes.contains(&other.home_slot(hash_with_fxhash(key))))
+            .collect()
+    }
+
+    pub fn into_iter_by_distance(self) -> impl Iterator<Item = (K, V)> {
+        let mut buckets: Vec<Bucket<K, V>> = self.array.into_iter().flatten().collect();
+        buckets.sort_by_key(|bucket| bucket.probe_length);
+        buckets.into_iter().map(|bucket| (bucket.key, bucket.value))
+    }
+
+    pub fn verify_after_resize(&self) -> Result<(), String> {
+        for (index, slot) in self.array.iter().enumerate() {
+            let Some(bucket) = slot else {
+                continue;
+            };
+            let hash = hash_with_fxhash(&bucket.key);
+            if self.probe(hash, &bucket.key).0 != Some(index) {
+                return Err(format!(
+                    "slot {} is not reachable by probing from home slot {} at capacity {}",
+                    index,
+                    self.home_slot(hash),
+                    self.capacity
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_dot(&self) -> String
+    where
+        K: std::fmt::Debug,
+    {
+        let mut dot = String::from("digraph RobinHashMap {\n");
+        for (index, slot) in self.array.iter().enumerate() {
+            let Some(bucket) = slot else {
+                continue;
+            };
+            dot.push_str(&format!(
+                "    slot{} [label=\"{}: {}\"];\n",
+                index,
+                index,
+                format!("{:?}", bucket.key).replace('"', "\\\"")
+            ));
+            if bucket.probe_length > 1 {
+                let home = (index + self.capacity - (bucket.probe_length - 1)) % self.capacity;
+                dot.push_str(&format!(
+                    "    slot{} -> slot{} [label=\"{}\"];\n",
+                    home,
+                    index,
+                    bucket.probe_length - 1
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn insert_batch(
+        &mut self,
+        pairs: impl IntoIterator<Item = (K, V)>,
+    ) -> Vec<InsertOutcome<V>> {
+        let pairs: Vec<(K, V)> = pairs.into_iter().collect();
+        self.reserve(pairs.len());
+        pairs
+            .into_iter()
+            .map(|(key, value)| match self.insert(key, value) {
+                Some(old) => InsertOutcome::Updated(old.value),
+                None => InsertOutcome::Inserted,
+            })
+            .collect()
+    }
+
+    pub fn contains_by(&self, hash: u64, eq: impl Fn(&K) -> bool) -> bool {
+        if let Some(bloom) = &self.bloom
+            && !bloom.may_contain(hash)
+        {
+            return false;
+        }
+        let mut index = self.home_slot(hash);
+        for psl in 1..=self.max_psl.min(self.capacity) {
+            match &self.array[index] {
+                None => return false,
+                Some(bucket) if bucket.probe_length < psl => return false,
+                Some(bucket) if eq(&bucket.key) => return true,
+                _ => {}
+            }
+            index = (index + 1) % self.capacity;
+        }
+        false
+    }
+
+    pub fn resizes_for(
+        initial_capacity: usize,
+        inserts: usize,
+        growth_factor: f64,
+        max_load: f64,
+    ) -> usize {
+        growth::resizes_for(initial_capacity, inserts, growth_factor, max_load)
+    }
+
+    pub fn home_hit_ratio(&self) -> f64 {
+        if self.len == 0 {
+            return 0.0;
+        }
+        let at_home = self
+            .array
+            .iter()
+            .flatten()
+            .filter(|bucket| bucket.probe_length == 1)
+            .count();
+        at_home as f64 / self.len as f64
+    }
+
+    pub fn cluster_of(&self, key: &K) -> Vec<(&K, &V)> {
+        let hash = hash_with_fxhash(key);
+        let index = self
+            .find_index_hashed(hash, key)
+            .unwrap_or_else(|| self.home_slot(hash));
+        if self.array[index].is_none() {
+            return Vec::new();
+        }
+        let mut start = index;
+        for _ in 1..self.capacity {
+            let previous = (start + self.capacity - 1) % self.capacity;
+            if self.array[previous].is_none() {
+                break;
+            }
+            start = previous;
+        }
+        (0..self.capacity)
+            .map(|offset| &self.array[(start + offset) % self.capacity])
+            .map_while(|slot| slot.as_ref())
+            .map(|bucket| (&bucket.key, &bucket.value))
+            .collect()
+    }
+
+    pub fn into_perfect(self) -> PerfectMap<K, V> {
+        PerfectMap::build(
+            self.array
+                .into_iter()
+                .flatten()
+                .map(|bucket| (bucket.key, bucket.value))
+                .collect(),
+        )
+    }
+
+    pub fn peak_len(&self) -> usize {
+        self.peak_len
+    }
+
+    // Falls back to a full scan when the key is absent, so this is O(n)
+    pub fn get_closest(&self, key: &K) -> Option<(&K, &V)>
+    where
+        K: Ord + Copy + TryInto<i128> + TryInto<u128>,
+    {
+        if let Some(index) = self.find_index(key) {
+            return self.array[index]
+                .as_ref()
+                .map(|bucket| (&bucket.key, &bucket.value));
+        }
+        self.entries()
+            .min_by_key(|&(candidate, _)| (numeric::integer_distance(*candidate, *key), *candidate))
+    }
+
+    pub fn value_size_histogram<F: Fn(&V) -> usize>(
+        &self,
+        size_of: F,
+        buckets: &[usize],
+    ) -> Vec<usize> {
+        debug_assert!(
+            buckets.windows(2).all(|pair| pair[0] < pair[1]),
+            "value_size_histogram requires bucket bounds in strictly ascending order"
+        );
+        // One bin per inclusive upper bound, plus a final bin for anything larger
+        let mut histogram = vec![0; buckets.len() + 1];
+        for (_, value) in self.entries() {
+            let size = size_of(value);
+            histogram[buckets.partition_point(|&bound| bound < size)] += 1;
+        }
+        histogram
+    }
+
+    pub fn fold<B, F: FnMut(B, &K, &V) -> B>(&self, init: B, mut f: F) -> B {
+        self.entries()
+            .fold(init, |acc, (key, value)| f(acc, key, value))
+    }
+
+    pub fn summary(&self) -> String {
+        let average_probe = if self.len == 0 {
+            0.0
+        } else {
+            self.total_probe_cost() as f64 / self.len as f64
+        };
+        format!(
+            "RobinHashMap len={} capacity={} load={:.2} max_psl={} avg_probe={:.2}",
+            self.len,
+            self.capacity,
+            self.load_factor(),
+            self.max_psl,
+            average_probe
+        )
+    }
+
+    pub fn keys_affected_by_shrink(&self, new_capacity: usize) -> usize {
+        assert!(new_capacity > 0, "cannot rehash into a zero-capacity table");
+        self.array
+            .iter()
+            .flatten()
+            .filter(|bucket| {
+                let hash = hash_with_fxhash(&bucket.key);
+                self.home_slot(hash) != self.home_slot_in(hash, new_capacity)
+            })
+            .count()
+    }
+
+    pub fn from_iter_with<I, F>(iter: I, mut merge: F) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(V, V) -> V,
+    {
+        let pairs: Vec<(K, V)> = iter.into_iter().collect();
+        let mut map = Self::new(capacity_for(pairs.len()));
+        for (key, value) in pairs {
+            // Merge in place: the bucket leaves its slot only for the duration of the merge
+            if let Some(index) = map.find_index(&key)
+                && let Some(bucket) = map.array[index].take()
+            {
+                map.array[index] = Some(Bucket {
+                    value: merge(bucket.value, value),
+                    ..bucket
+                });
+            } else {
+                map.insert(key, value);
+            }
+        }
+        map
+    }
+
+    // Same hash as prehash, so it can be passed to get_prehashed; the reseed salt only enters slot selection
+    pub fn hash_of(&self, key: &K) -> u64 {
+        hash_with_fxhash(key)
+    }
+
+    pub fn get_each_mut(&mut self, keys: &[K]) -> Vec<Option<&mut V>> {
+        let indices: Vec<Option<usize>> = keys.iter().map(|key| self.find_index(key)).collect();
+        // Taking each reference out of the slot list leaves None behind for repeated keys
+        let mut values: Vec<Option<&mut V>> = self
+            .array
+            .iter_mut()
+            .map(|slot| slot.as_mut().map(|bucket| &mut bucket.value))
+            .collect();
+        indices
+            .into_iter()
+            .map(|index| index.and_then(|index| values[index].take()))
+            .collect()
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        let mut displacement_histogram = Vec::new();
+        for bucket in self.array.iter().flatten() {
+            let displacement = bucket.probe_length - 1;
+            if displacement >= displacement_histogram.len() {
+                displacement_histogram.resize(displacement + 1, 0);
+            }
+            displacement_histogram[displacement] += 1;
+        }
+        let violations = [self.check_invariants(), self.verify_after_resize()]
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        Diagnostics {
+            len: self.len,
+            capacity: self.capacity,
+            load_factor: self.load_factor(),
+            tombstones: 0,
+            displacement_histogram,
+            stats: self.lifetime_stats(),
+            home_hit_ratio: self.home_hit_ratio(),
+            violations,
+        }
+    }
+
+    pub fn map_keys<K2, F>(self, mut f: F) -> RobinHashMap<K2, V>
+    where
+        K2: Hash + Eq + Clone,
+        F: FnMut(K) -> K2,
+    {
+        let mut mapped = RobinHashMap::new(capacity_for(self.len));
+        for bucket in self.array.into_iter().flatten() {
+            mapped.insert(f(bucket.key), bucket.value);
+        }
+        mapped
+    }
+
+    // Nearest-rank percentile with p as a fraction in 0.0..=1.0; 0 for an empty map
+    pub fn probe_length_percentile(&self, p: f64) -> usize {
+        if self.len == 0 {
+            return 0;
+        }
+        let mut histogram = vec![0usize; self.max_psl + 1];
+        for bucket in self.array.iter().flatten() {
+            histogram[bucket.probe_length] += 1;
+        }
+        let rank = ((p.clamp(0.0, 1.0) * self.len as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (probe_length, &count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return probe_length;
+            }
+        }
+        self.max_psl
+    }
+
+    pub fn increment(&mut self, key: K, by: V) -> &V
+    where
+        V: std::ops::AddAssign + Default,
+    {
+        let index = self.find_or_insert_with(hash_with_fxhash(&key), key, V::default);
+        let bucket = self.array[index].as_mut().unwrap();
+        bucket.value += by;
+        &bucket.value
+    }
+
+    pub fn balance_ratio(&self) -> f64 {
+        let midpoint = self.capacity / 2;
+        let lower = self.home_indices().filter(|&home| home < midpoint).count();
+        let upper = self.len - lower;
+        match (lower, upper) {
+            (0, 0) => 1.0,
+            (_, 0) => f64::INFINITY,
+            _ => lower as f64 / upper as f64,
+        }
+    }
+
+    pub fn get_cloned(&self, key: &K) -> Option<V> {
+        self.get(key).cloned()
+    }
+
+    // Slots per 64-byte cache line, or 1 when a single slot spans more than a line
+    pub fn cache_alignment_unit() -> usize {
+        (CACHE_LINE_BYTES / std::mem::size_of::<Option<Bucket<K, V>>>().max(1)).max(1)
+    }
+
+    pub fn reserve_cache_aligned(&mut self, additional: usize) {
+        let unit = Self::cache_alignment_unit();
+        let needed = capacity_for(self.len + additional);
+        if needed > self.capacity {
+            self.rebuild(needed.max(self.capacity * 2).next_multiple_of(unit));
+        } else if !self.capacity.is_multiple_of(unit) {
+            self.rebuild(self.capacity.next_multiple_of(unit));
+        }
+    }
+
+    pub fn iter_shuffled(&self, seed: u64) -> impl Iterator<Item = (&K, &V)> {
+        let mut indices: Vec<usize> = (0..self.capacity)
+            .filter(|&index| self.array[index].is_some())
+            .collect();
+        SplitMix64::new(seed).shuffle(&mut indices);
+        indices.into_iter().filter_map(|index| {
+            self.array[index]
+                .as_ref()
+                .map(|bucket| (&bucket.key, &bucket.value))
+        })
+    }
+
+    pub fn key_jaccard(&self, other: &Self) -> f64 {
+        let shared = self
+            .entries()
+            .filter(|(key, _)| other.contains(key))
+            .count();
+        let union = self.len + other.len - shared;
+        if union == 0 {
+            return 1.0;
+        }
+        shared as f64 / union as f64
+    }
+
+    // Only a hint: a no-op on targets without a prefetch intrinsic
+    pub fn prefetch(&self, key: &K) {
+        if self.capacity == 0 {
+            return;
+        }
+        let slot = self
+            .array
+            .as_ptr()
+            .wrapping_add(self.home_slot(hash_with_fxhash(key)));
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_MM_HINT_T0, _mm_prefetch};
+            // SAFETY: prefetch never faults, even on an invalid address, and SSE is baseline on x86_64
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(slot as *const i8) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = slot;
+    }
+
+    pub fn export_keys(&self) -> Vec<K> {
+        self.entries().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn retain_only_keys(&mut self, keys: &[K]) {
+        let keep: HashSet<&K> = keys.iter().collect();
+        self.retain_visit(|key, _| {
+            if keep.contains(key) {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        });
+    }
+
+    // Counts the slot array only; heap memory owned by keys and values is unaffected by shrinking
+    pub fn shrink_savings_bytes(&self) -> usize {
+        self.capacity.saturating_sub(capacity_for(self.len))
+            * std::mem::size_of::<Option<Bucket<K, V>>>()
+    }
+
+    pub fn to_canonical(&self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<(K, V)> = self
+            .entries()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    // Inserting in key order at a capacity fixed by the length gives the same layout on every run
+    pub fn from_canonical(mut entries: Vec<(K, V)>) -> Self
+    where
+        K: Ord,
+    {
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut map = Self::new(capacity_for(entries.len()));
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+
+    // Runs are reported in slot order, so a cluster wrapping past the last slot appears as two runs
+    pub fn occupied_runs(&self) -> impl Iterator<Item = (usize, Vec<(&K, &V)>)> {
+        let mut runs: Vec<(usize, Vec<(&K, &V)>)> = Vec::new();
+        let mut current: Option<(usize, Vec<(&K, &V)>)> = None;
+        for (index, slot) in self.array.iter().enumerate() {
+            match (slot, &mut current) {
+                (Some(bucket), Some((_, entries))) => entries.push((&bucket.key, &bucket.value)),
+                (Some(bucket), None) => current = Some((index, vec![(&bucket.key, &bucket.value)])),
+                (None, _) => runs.extend(current.take()),
+            }
+        }
+        runs.extend(current);
+        runs.into_iter()
+    }
+
+    pub fn get_or_compute<F: FnOnce() -> V>(&mut self, key: K, compute: F) -> &V {
+        let index = self.find_or_insert_with(hash_with_fxhash(&key), key, compute);
+        &self.array[index].as_ref().unwrap().value
+    }
+
+    pub fn predict_worst_probes_after_resize(&self, new_capacity: usize) -> usize {
+        assert!(
+            new_capacity >= self.len && new_capacity > 0,
+            "capacity {} cannot hold {} entries",
+            new_capacity,
+            self.len
+        );
+        // Replays rebuild's insertion order, tracking only each slot's probe length
+        let mut probe_lengths: Vec<Option<usize>> = vec![None; new_capacity];
+        for bucket in self.array.iter().flatten() {
+            let mut index = self.home_slot_in(hash_with_fxhash(&bucket.key), new_capacity);
+            let mut incoming = 1;
+            loop {
+                match &mut probe_lengths[index] {
+                    None => {
+                        probe_lengths[index] = Some(incoming);
+                        break;
+                    }
+                    Some(resident) if *resident < incoming => {
+                        std::mem::swap(resident, &mut incoming)
+                    }
+                    _ => {}
+                }
+                index = (index + 1) % new_capacity;
+                incoming += 1;
+            }
+        }
+        probe_lengths.into_iter().flatten().max().unwrap_or(0)
+    }
+
+    pub fn retain_counting<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> usize {
+        let before = self.len;
+        self.retain_visit(|key, value| {
+            if f(key, value) {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        });
+        before - self.len
+    }
+
+    // Successful get calls per live key, kept across resizes and reset when a key is deleted;
+    // all zero unless the map was built with_stats
+    pub fn entry_access_counts(&self) -> Vec<(&K, usize)> {
+        self.array
+            .iter()
+            .flatten()
+            .map(|bucket| (&bucket.key, bucket.reads.get()))
+            .collect()
+    }
+
+    pub fn union_with<F: FnMut(&V, &V) -> V>(&self, other: &Self, mut combine: F) -> Self {
+        let mut union = Self::new(capacity_for(self.len + other.len));
+        for (key, value) in self.entries() {
+            union.insert(key.clone(), value.clone());
+        }
+        for (key, value) in other.entries() {
+            match union.get_mut(key) {
+                Some(existing) => *existing = combine(existing, value),
+                None => {
+                    union.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        union
+    }
+
+    // Long probes below the max load point to colliding keys rather than a full table; reseed to recover
+    pub fn is_under_collision_attack(&self, threshold_probe: usize) -> bool {
+        self.load_factor() < MAX_LOAD_FACTOR && self.max_displacement() + 1 > threshold_probe
+    }
+
+    pub fn freeze_capacity(&mut self) -> CapacityGuard<'_, Self> {
+        CapacityGuard { map: self }
+    }
+}
+
+impl<K, V> CapacityGuard<'_, RobinHashMap<K, V>>
+where
+    K: Eq + Clone + Hash,
+    V: Clone,
+{
+    // Updates always succeed; a new key is refused once it would trigger a resize
+    pub fn insert(&mut self, key: K, value: V) -> Result<InsertOutcome<V>, TableFull<K, V>> {
+        if let Some(index) = self.map.find_index(&key)
+            && let Some(bucket) = &mut self.map.array[index]
+        {
+            return Ok(InsertOutcome::Updated(std::mem::replace(
+                &mut bucket.value,
+                value,
+            )));
+        }
+        if self.map.len >= self.map.resize_threshold() {
+            return Err(TableFull { key, value });
+        }
+        self.map.insert(key, value);
+        Ok(InsertOutcome::Inserted)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        self.map.delete(key)
+    }
+}
+
+impl RobinHashMap<u64, u64> {
+    // Average probe length of live entries at evenly spaced loads up to MAX_LOAD_FACTOR
+    pub fn throughput_curve(capacity: usize, hasher_seed: u64, samples: usize) -> Vec<(f64, f64)> {
+        if capacity == 0 {
+            return Vec::new();
+        }
+        let mut map = Self::new(capacity);
+        map.salt = hasher_seed;
+        let mut keys = SplitMix64::new(hasher_seed);
+        let mut curve = Vec::with_capacity(samples);
+        for sample in 1..=samples {
+            let target =
+                (capacity as f64 * MAX_LOAD_FACTOR * sample as f64 / samples as f64) as usize;
+            while map.len < target {
+                let key = keys.next_u64();
+                map.insert(key, key);
+            }
+            if map.len > 0 {
+                curve.push((
+                    map.load_factor(),
+                    map.total_probe_cost() as f64 / map.len as f64,
+                ));
+            }
+        }
+        curve
     }
 }
+
+impl<K, V> RobinHashMap<K, Weak<V>>
+where
+    K: Eq + Clone + Hash,
+{
+    pub fn insert_rc(&mut self, key: K, value: &Rc<V>) -> Option<Bucket<K, Weak<V>>> {
+        self.insert(key, Rc::downgrade(value))
+    }
+
+    pub fn get_upgraded(&self, key: &K) -> Option<Rc<V>> {
+        self.get(key).and_then(Weak::upgrade)
+    }
+
+    pub fn purge_dead(&mut self) {
+        let dead: Vec<K> = self
+            .array
+            .iter()
+            .flatten()
+            .filter(|bucket| bucket.value.strong_count() == 0)
+            .map(|bucket| bucket.key.clone())
+            .collect();
+        for key in &dead {
+            self.delete(key);
+        }
+    }
+}
+
+impl<K, T> RobinHashMap<K, Vec<T>>
+where
+    K: Eq + Clone + Hash,
+    T: Clone,
+{
+    pub fn group_by<I, F>(items: I, mut key_fn: F) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&T) -> K,
+    {
+        let mut groups = Self::new(capacity_for(0));
+        for item in items {
+            let key = key_fn(&item);
+            match groups.get_mut(&key) {
+                Some(group) => group.push(item),
+                None => {
+                    groups.reserve(1);
+                    groups.insert(key, vec![item]);
+                }
+            }
+        }
+        groups
+    }
+}
+
+impl<K, V> RobinHashMap<K, Rc<V>>
+where
+    K: Eq + Clone + Hash,
+{
+    pub fn intern_values(&mut self) -> usize
+    where
+        V: Hash + Eq,
+    {
+        let mut pool: HashSet<Rc<V>> = HashSet::new();
+        for bucket in self.array.iter_mut().flatten() {
+            match pool.get(&bucket.value) {
+                Some(shared) => bucket.value = Rc::clone(shared),
+                None => {
+                    pool.insert(Rc::clone(&bucket.value));
+                }
+            }
+        }
+        pool.len()
+    }
+}
+
+#[cfg(test)]
+mod tests;
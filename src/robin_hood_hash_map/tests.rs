@@ -0,0 +1,1575 @@
+use super::*;
+
+// Every key hashes identically, as if the map used a constant hasher
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Colliding(u32);
+
+impl Hash for Colliding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        0u8.hash(state);
+    }
+}
+
+#[test]
+fn purge_dead_drops_entries_whose_values_were_freed() {
+    let mut map: RobinHashMap<u32, Weak<String>> = RobinHashMap::new(16);
+    let live = Rc::new(String::from("live"));
+    let dead = Rc::new(String::from("dead"));
+    map.insert_rc(1, &live);
+    map.insert_rc(2, &dead);
+    drop(dead);
+
+    assert_eq!(map.get_upgraded(&1), Some(Rc::clone(&live)));
+    assert_eq!(map.get_upgraded(&2), None);
+    map.purge_dead();
+    assert_eq!(map.len(), 1);
+    assert!(map.contains(&1));
+    assert!(!map.contains(&2));
+}
+
+// Installs buckets verbatim, bypassing insert, so tests can build exact or corrupt layouts
+fn from_raw<K, V>(array: Vec<Option<Bucket<K, V>>>) -> RobinHashMap<K, V>
+where
+    K: Eq + Clone + Hash,
+    V: Clone,
+{
+    let mut map = RobinHashMap::new(array.len());
+    map.len = array.iter().flatten().count();
+    map.max_psl = array
+        .iter()
+        .flatten()
+        .map(|bucket| bucket.probe_length)
+        .max()
+        .unwrap_or(0);
+    map.array = array;
+    map
+}
+
+fn bucket<K, V>(key: K, value: V, probe_length: usize) -> Option<Bucket<K, V>> {
+    Some(Bucket {
+        key,
+        value,
+        probe_length,
+        reads: ReadCounter::default(),
+    })
+}
+
+fn colliding_home(capacity: usize) -> usize {
+    RobinHashMap::<Colliding, u32>::new(capacity).home_slot(hash_with_fxhash(&Colliding(0)))
+}
+
+fn populated(keys: std::ops::Range<u32>) -> RobinHashMap<u32, u32> {
+    let mut map = RobinHashMap::new(64);
+    for key in keys {
+        map.insert(key, key * 10);
+    }
+    map
+}
+
+#[test]
+fn apply_updates_only_touches_existing_keys() {
+    let mut base = populated(0..4);
+    let mut updates = RobinHashMap::new(16);
+    updates.insert(1, 100);
+    updates.insert(9, 900);
+
+    base.apply_updates(&updates);
+    assert_eq!(base.len(), 4);
+    assert_eq!(base.get(&1), Some(&100));
+    assert_eq!(base.get(&2), Some(&20));
+    assert_eq!(base.get(&9), None);
+}
+
+#[test]
+fn best_case_lookup_finds_a_home_slot_entry() {
+    let map = populated(0..20);
+    let (key, distance) = map.best_case_lookup().unwrap();
+    assert_eq!(distance, 0);
+    assert!(map.contains(key));
+    assert_eq!(RobinHashMap::<u32, u32>::new(8).best_case_lookup(), None);
+}
+
+#[test]
+fn shard_places_every_entry_in_exactly_one_shard() {
+    let shards = populated(0..100).shard(4);
+    assert_eq!(shards.len(), 4);
+    for key in 0..100 {
+        let holders: Vec<usize> = (0..4).filter(|&i| shards[i].contains(&key)).collect();
+        assert_eq!(holders.len(), 1);
+        assert_eq!(shards[holders[0]].get(&key), Some(&(key * 10)));
+    }
+    // The same key always lands in the same shard
+    let again = populated(0..100).shard(4);
+    for key in 0..100 {
+        assert!((0..4).all(|i| shards[i].contains(&key) == again[i].contains(&key)));
+    }
+    assert_eq!(shards.iter().map(RobinHashMap::len).sum::<usize>(), 100);
+}
+
+#[test]
+#[should_panic(expected = "zero shards")]
+fn shard_rejects_zero_shards() {
+    populated(0..4).shard(0);
+}
+
+#[test]
+fn get_or_falls_back_to_the_supplied_default() {
+    let map = populated(0..4);
+    let default = 7;
+    assert_eq!(map.get_or(&2, &default), &20);
+    assert!(std::ptr::eq(map.get_or(&99, &default), &default));
+    assert_eq!(map.len(), 4);
+}
+
+#[test]
+fn is_healthy_tracks_load_and_probe_lengths() {
+    assert!(populated(0..20).is_healthy());
+    // insert_unique never grows, so this packs the table completely
+    let mut overfilled = RobinHashMap::new(10);
+    for key in 0..10u32 {
+        overfilled.insert_unique(key, key);
+    }
+    assert_eq!(overfilled.load_factor(), 1.0);
+    assert!(!overfilled.is_healthy());
+}
+
+#[test]
+fn move_entry_to_transfers_the_entry() {
+    let mut source = populated(0..8);
+    let mut dest = RobinHashMap::new(8);
+    assert!(source.move_entry_to(&3, &mut dest));
+    assert!(!source.contains(&3));
+    assert_eq!(dest.get(&3), Some(&30));
+    assert!(!source.move_entry_to(&3, &mut dest));
+    assert_eq!(source.check_invariants(), Ok(()));
+}
+
+#[test]
+fn count_values_counts_matching_entries() {
+    let map = populated(0..10);
+    assert_eq!(map.count_values(|&value| value > 50), 4);
+    assert_eq!(map.count_values(|_| true), 10);
+}
+
+#[test]
+fn get_mut_or_panic_returns_a_mutable_reference() {
+    let mut map = populated(0..4);
+    *map.get_mut_or_panic(&2) += 1;
+    assert_eq!(map.get(&2), Some(&21));
+}
+
+#[test]
+#[should_panic(expected = "key 42 not found")]
+fn get_mut_or_panic_names_the_missing_key() {
+    populated(0..4).get_mut_or_panic(&42);
+}
+
+#[test]
+fn fill_histogram_concentrates_colliding_keys() {
+    let mut map = RobinHashMap::new(64);
+    for id in 0..8 {
+        map.insert(Colliding(id), id);
+    }
+    let histogram = map.fill_histogram(4);
+    assert_eq!(histogram.iter().sum::<usize>(), 8);
+    assert!(histogram.iter().filter(|&&count| count > 0).count() <= 2);
+    assert!(*histogram.iter().max().unwrap() >= 4);
+}
+
+#[test]
+fn fill_histogram_spreads_well_hashed_keys() {
+    let mut map = RobinHashMap::new(2000);
+    for key in 0..1000u32 {
+        map.insert(key, key);
+    }
+    let histogram = map.fill_histogram(4);
+    assert_eq!(histogram.iter().sum::<usize>(), 1000);
+    assert!(histogram.iter().all(|&count| (200..=300).contains(&count)));
+    assert!(
+        RobinHashMap::<u32, u32>::new(8)
+            .fill_histogram(0)
+            .is_empty()
+    );
+}
+
+#[test]
+fn take_leaves_the_key_with_a_default_value() {
+    let mut map = populated(0..4);
+    assert_eq!(map.take(&1), Some(10));
+    assert_eq!(map.get(&1).copied(), Some(0));
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.take(&9), None);
+}
+
+#[test]
+fn get_prehashed_agrees_with_get() {
+    let map = populated(0..10);
+    let keys = [0, 3, 9, 42];
+    for (hash, key) in RobinHashMap::<u32, u32>::prehash(&keys)
+        .into_iter()
+        .zip(keys)
+    {
+        assert_eq!(map.get_prehashed(hash, &key), map.get(&key));
+    }
+}
+
+#[test]
+fn dedup_removes_a_duplicated_key() {
+    let home = colliding_home(8);
+    let mut array = vec![None; 8];
+    array[home] = bucket(Colliding(1), 1, 1);
+    array[(home + 1) % 8] = bucket(Colliding(2), 2, 2);
+    array[(home + 2) % 8] = bucket(Colliding(1), 3, 3);
+    let mut map = from_raw(array);
+
+    assert_eq!(map.dedup(), 1);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&Colliding(1)), Some(&1));
+    assert_eq!(map.get(&Colliding(2)), Some(&2));
+    assert_eq!(map.check_invariants(), Ok(()));
+    assert_eq!(map.dedup(), 0);
+}
+
+#[test]
+fn total_probe_cost_sums_displacement_plus_one() {
+    let mut map = RobinHashMap::new(16);
+    for id in 0..3 {
+        map.insert(Colliding(id), id);
+    }
+    // Displacements 0, 1 and 2 cost 1 + 2 + 3 probes
+    assert_eq!(map.total_probe_cost(), 6);
+    assert_eq!(RobinHashMap::<u32, u32>::new(4).total_probe_cost(), 0);
+}
+
+// Keys whose home slot is `home` in an unsalted map of the given capacity
+fn keys_homed_at(capacity: usize, home: usize, count: usize) -> Vec<u32> {
+    let map = RobinHashMap::<u32, u32>::new(capacity);
+    (0u32..)
+        .filter(|key| map.home_slot(hash_with_fxhash(key)) == home)
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn reseed_keeps_keys_findable_and_moves_home_slots() {
+    let mut map = populated(0..40);
+    let homes_before: Vec<usize> = (0..40)
+        .map(|key| map.home_slot(hash_with_fxhash(&key)))
+        .collect();
+    map.reseed(0x5eed);
+    for key in 0..40 {
+        assert_eq!(map.get(&key), Some(&(key * 10)));
+    }
+    assert!((0..40).any(|key| map.home_slot(hash_with_fxhash(&key)) != homes_before[key as usize]));
+    assert_eq!(map.check_invariants(), Ok(()));
+}
+
+#[test]
+fn reseed_separates_keys_that_shared_a_home_slot() {
+    let keys = keys_homed_at(64, 0, 8);
+    let mut map = RobinHashMap::new(64);
+    for &key in &keys {
+        map.insert(key, key);
+    }
+    assert_eq!(map.max_displacement(), 7);
+    map.reseed(0x5eed);
+    let homes: HashSet<usize> = keys
+        .iter()
+        .map(|key| map.home_slot(hash_with_fxhash(key)))
+        .collect();
+    assert!(homes.len() > 1);
+    assert!(map.max_displacement() < 7);
+}
+
+#[test]
+fn from_sorted_unique_holds_every_pair() {
+    let pairs: Vec<(u32, u32)> = (0..50).map(|key| (key, key * 2)).collect();
+    let map = RobinHashMap::from_sorted_unique(&pairs);
+    assert_eq!(map.len(), 50);
+    for (key, value) in &pairs {
+        assert_eq!(map.get(key).copied(), Some(*value));
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "strictly ascending")]
+fn from_sorted_unique_rejects_unsorted_input() {
+    RobinHashMap::from_sorted_unique(&[(2u32, 0u32), (1, 0)]);
+}
+
+#[test]
+fn filter_keys_yields_entries_in_the_range() {
+    let map = populated(0..20);
+    let mut found: Vec<(u32, u32)> = map
+        .filter_keys(|key| (5..8).contains(key))
+        .map(|(&key, &value)| (key, value))
+        .collect();
+    found.sort();
+    assert_eq!(found, vec![(5, 50), (6, 60), (7, 70)]);
+}
+
+#[test]
+fn lifetime_stats_count_a_mixed_workload() {
+    let mut map = RobinHashMap::new(16).with_stats();
+    // Three keys sharing a home slot take 1, 2 and 3 probes; the overwrite finds its key in 2
+    for id in 0..3 {
+        map.insert(Colliding(id), id);
+    }
+    map.insert(Colliding(1), 10);
+    // get: 2 slots to the key; a miss stops once max_psl (3) slots have been examined
+    // delete: 3 slots to find the key
+    map.get(&Colliding(1));
+    map.get(&Colliding(7));
+    map.delete(&Colliding(2));
+    let stats = map.lifetime_stats();
+    assert_eq!(stats.insert_count, 3);
+    assert_eq!(stats.get_count, 2);
+    assert_eq!(stats.delete_count, 1);
+    assert_eq!(stats.total_probes, 16);
+
+    // Rehashing the two survivors is not counted; the next new key walks past both of them
+    map.reserve(100);
+    map.insert(Colliding(5), 5);
+    let stats = map.lifetime_stats();
+    assert_eq!(stats.resize_count, 1);
+    assert_eq!(stats.insert_count, 4);
+    assert_eq!(stats.total_probes, 19);
+}
+
+#[test]
+fn get_with_index_points_at_the_matching_slot() {
+    let map = populated(0..10);
+    let (index, value) = map.get_with_index(&4).unwrap();
+    assert_eq!(value, &40);
+    assert!(matches!(
+        map.raw_iter().nth(index),
+        Some(SlotView::Occupied(&4, &40, _))
+    ));
+    assert_eq!(map.get_with_index(&99), None);
+}
+
+#[test]
+fn enforce_max_probe_grows_until_the_limit_holds() {
+    let keys = keys_homed_at(64, 0, 8);
+    let mut map = RobinHashMap::new(64);
+    for &key in &keys {
+        map.insert(key, key);
+    }
+    assert_eq!(map.max_displacement(), 7);
+    assert!(map.enforce_max_probe(1));
+    assert!(map.max_displacement() <= 1);
+    assert!(map.capacity() > 128);
+    for &key in &keys {
+        assert_eq!(map.get_with_index(&key).map(|(_, &value)| value), Some(key));
+    }
+    assert!(!map.enforce_max_probe(1));
+}
+
+#[test]
+fn enforce_max_probe_gives_up_on_identical_hashes() {
+    let mut map = RobinHashMap::new(64);
+    for id in 0..8 {
+        map.insert(Colliding(id), id);
+    }
+    assert!(map.enforce_max_probe(1));
+    assert_eq!(map.capacity(), 128);
+    assert_eq!(map.max_displacement(), 7);
+}
+
+#[test]
+fn apply_delta_replays_the_changes_onto_the_base() {
+    let base = populated(0..10);
+    let mut mutated = base.clone();
+    mutated.delete(&3);
+    mutated.insert(4, 400);
+    mutated.insert(20, 200);
+    let delta = mutated.delta_since(&base);
+    assert_eq!(delta.added, vec![(20, 200)]);
+    assert_eq!(delta.changed, vec![(4, 400)]);
+    assert_eq!(delta.removed, vec![3]);
+
+    let mut replayed = base.clone();
+    replayed.apply_delta(delta);
+    assert_eq!(replayed.to_canonical(), mutated.to_canonical());
+}
+
+#[test]
+fn get_first_falls_back_along_the_key_list() {
+    let mut map = RobinHashMap::new(8);
+    map.insert("general", 1);
+    assert_eq!(
+        map.get_first(&["specific", "general"]),
+        Some((&"general", &1))
+    );
+    map.insert("specific", 2);
+    assert_eq!(
+        map.get_first(&["specific", "general"]),
+        Some((&"specific", &2))
+    );
+    assert_eq!(map.get_first(&["missing"]), None);
+}
+
+#[test]
+fn prepare_for_fits_the_keys_without_resizing() {
+    let keys: Vec<u32> = (0..1000).collect();
+    let mut map = RobinHashMap::prepare_for(&keys).with_stats();
+    assert!(map.is_empty());
+    for &key in &keys {
+        map.insert(key, key);
+    }
+    assert_eq!(map.len(), 1000);
+    assert_eq!(map.lifetime_stats().resize_count, 0);
+    assert!(map.load_factor() < MAX_LOAD_FACTOR);
+}
+
+#[test]
+fn prepare_for_sizes_for_distinct_keys() {
+    let repeated: Vec<u32> = (0..300).map(|key| key % 100).collect();
+    assert_eq!(
+        RobinHashMap::<u32, u32>::prepare_for(&repeated).capacity(),
+        capacity_for(100)
+    );
+}
+
+#[test]
+fn would_displace_matches_the_swaps_made_by_insert() {
+    let first = keys_homed_at(64, 0, 5);
+    let second = [keys_homed_at(64, 2, 1)[0], keys_homed_at(64, 5, 1)[0]];
+    let mut map = RobinHashMap::new(64);
+    for &key in first[..4].iter().chain(&second) {
+        map.insert(key, key);
+    }
+    let slots = |map: &RobinHashMap<u32, u32>| -> Vec<usize> {
+        first[..4]
+            .iter()
+            .chain(&second)
+            .map(|key| map.get_with_index(key).unwrap().0)
+            .collect()
+    };
+    let before = slots(&map);
+    let predicted = map.would_displace(&first[4]);
+    map.insert(first[4], 0);
+    let after = slots(&map);
+    let moved = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+    assert_eq!(predicted, moved);
+    assert_eq!(moved, 2);
+    assert_eq!(map.would_displace(&first[0]), 0);
+}
+
+#[test]
+fn clone_with_capacity_copies_into_the_requested_size() {
+    let map = populated(0..10);
+    let copy = map.clone_with_capacity(256);
+    assert_eq!(copy.capacity(), 256);
+    assert_eq!(copy.to_canonical(), map.to_canonical());
+    assert_eq!(map.capacity(), 64);
+}
+
+#[test]
+#[should_panic(expected = "cannot hold 10 entries")]
+fn clone_with_capacity_rejects_a_table_too_small() {
+    populated(0..10).clone_with_capacity(5);
+}
+
+#[test]
+fn raw_iter_reports_each_slot_in_memory_order() {
+    let home = colliding_home(4);
+    let mut array = vec![None; 4];
+    array[home] = bucket(Colliding(1), 10, 1);
+    array[(home + 1) % 4] = bucket(Colliding(2), 20, 2);
+    let map = from_raw(array);
+
+    let mut expected = vec![SlotView::Empty; 4];
+    expected[home] = SlotView::Occupied(&Colliding(1), &10, 0);
+    expected[(home + 1) % 4] = SlotView::Occupied(&Colliding(2), &20, 1);
+    assert_eq!(map.raw_iter().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn bloom_rejects_absent_keys_without_probing() {
+    let mut map = RobinHashMap::new(256).with_stats().with_bloom();
+    for key in 0..10u32 {
+        map.insert(key, key);
+    }
+    let probes = |map: &RobinHashMap<u32, u32>| map.lifetime_stats().total_probes;
+    let mut short_circuited = 0;
+    for key in 1000..1100u32 {
+        let before = probes(&map);
+        assert_eq!(map.get_with_index(&key), None);
+        if probes(&map) == before {
+            short_circuited += 1;
+        }
+    }
+    assert!(short_circuited >= 90);
+
+    // Rebuilt on resize, so present keys still resolve
+    map.reserve(1000);
+    for key in 0..10u32 {
+        assert_eq!(map.get_with_index(&key).map(|(_, &value)| value), Some(key));
+    }
+    // Cleared with the map, so former keys are rejected outright
+    map.clear();
+    let before = probes(&map);
+    assert_eq!(map.get_with_index(&3), None);
+    assert_eq!(probes(&map), before);
+}
+
+#[test]
+fn swap_values_exchanges_two_present_values() {
+    let mut map = populated(0..4);
+    assert!(map.swap_values(&1, &2));
+    assert_eq!(map.get_cloned(&1), Some(20));
+    assert_eq!(map.get_cloned(&2), Some(10));
+}
+
+#[test]
+fn swap_values_leaves_the_map_alone_for_a_missing_key() {
+    let mut map = populated(0..4);
+    assert!(!map.swap_values(&1, &9));
+    assert!(!map.swap_values(&1, &1));
+    assert_eq!(map.get_cloned(&1), Some(10));
+}
+
+#[test]
+fn top_n_returns_the_largest_values_in_descending_order() {
+    let mut map = RobinHashMap::new(16);
+    for (name, score) in [("ann", 5), ("bob", 9), ("cy", 1), ("dee", 7), ("eve", 8)] {
+        map.insert(name, score);
+    }
+    assert_eq!(map.top_n(3), vec![(&"bob", &9), (&"eve", &8), (&"dee", &7)]);
+    assert!(map.top_n(0).is_empty());
+    assert_eq!(map.top_n(10).len(), 5);
+}
+
+#[test]
+fn handle_resolves_after_a_resize() {
+    let mut map = RobinHashMap::new(4);
+    let handle = map.insert_handle(7u32, 70u32);
+    assert_eq!(map.get_by_handle(&handle), Some(&70));
+    for key in 100..200 {
+        map.insert_handle(key, key);
+    }
+    assert!(map.capacity() > 4);
+    assert_eq!(handle.key(), &7);
+    assert_eq!(map.get_by_handle(&handle), Some(&70));
+}
+
+#[test]
+fn home_slot_entropy_separates_good_and_constant_hashes() {
+    let mut spread = RobinHashMap::new(256);
+    let mut clustered = RobinHashMap::new(256);
+    for id in 0..64u32 {
+        spread.insert(id, id);
+        clustered.insert(Colliding(id), id);
+    }
+    assert!(spread.home_slot_entropy() > 5.0);
+    assert!(clustered.home_slot_entropy().abs() < 1e-9);
+}
+
+#[test]
+fn invert_groups_keys_by_shared_value() {
+    let mut map = RobinHashMap::new(16);
+    for (key, value) in [(1u32, 'a'), (2, 'b'), (3, 'a'), (4, 'a')] {
+        map.insert(key, value);
+    }
+    let inverted = map.invert();
+    let mut shared = inverted.get(&'a').unwrap().clone();
+    shared.sort();
+    assert_eq!(shared, vec![1, 3, 4]);
+    assert_eq!(inverted.get(&'b'), Some(&vec![2]));
+    assert_eq!(inverted.len(), 2);
+}
+
+#[test]
+fn retain_visit_removes_until_told_to_stop() {
+    let mut map = populated(0..20);
+    let mut removed = 0;
+    map.retain_visit(|_, &value| {
+        if removed == 5 {
+            VisitAction::Stop
+        } else if value % 20 == 0 {
+            removed += 1;
+            VisitAction::Remove
+        } else {
+            VisitAction::Keep
+        }
+    });
+    assert_eq!(map.len(), 15);
+    let evens_left = (0..20u32)
+        .filter(|key| key % 2 == 0 && map.get_cloned(key).is_some())
+        .count();
+    assert_eq!(evens_left, 5);
+    assert!(
+        (0..20u32)
+            .filter(|key| key % 2 == 1)
+            .all(|key| map.get_cloned(&key).is_some())
+    );
+    assert_eq!(map.check_invariants(), Ok(()));
+}
+
+#[test]
+fn crossing_the_resize_threshold_grows_the_table() {
+    let mut map = RobinHashMap::new(50).with_stats();
+    let threshold = map.resize_threshold();
+    assert_eq!(threshold, (50.0 * MAX_LOAD_FACTOR) as usize);
+    for key in 0..threshold as u32 {
+        map.insert(key, key);
+    }
+    assert_eq!(map.capacity(), 50);
+    // Updating a present key never grows the table
+    map.insert(0, 1);
+    assert_eq!(map.capacity(), 50);
+    map.insert(threshold as u32, 0);
+    assert_eq!(map.lifetime_stats().resize_count, 1);
+    assert!(map.capacity() >= 100);
+    assert!(map.len() < map.resize_threshold());
+}
+
+#[test]
+fn insert_into_an_empty_table_allocates() {
+    let mut map = RobinHashMap::new(0);
+    map.insert(1u32, 1u32);
+    assert!(map.capacity() > 0);
+    assert_eq!(map.get_cloned(&1), Some(1));
+}
+
+#[test]
+fn verified_clone_matches_the_original() {
+    let map = populated(0..30);
+    let copy = map.verified_clone();
+    assert_eq!(copy.to_canonical(), map.to_canonical());
+    assert_eq!(copy.capacity(), map.capacity());
+}
+
+#[test]
+fn probe_overhead_is_zero_at_home_and_positive_when_displaced() {
+    let keys = keys_homed_at(16, 4, 2);
+    let mut map = RobinHashMap::new(16);
+    map.insert(keys[0], 0);
+    map.insert(keys[1], 1);
+    assert_eq!(map.probe_overhead(&keys[0]), Some(0));
+    assert_eq!(map.probe_overhead(&keys[1]), Some(1));
+    assert_eq!(map.probe_overhead(&999), None);
+}
+
+#[test]
+fn group_by_keeps_items_in_encounter_order() {
+    let groups = RobinHashMap::group_by([3, 4, 7, 10, 1, 8], |item| item % 2 == 0);
+    assert_eq!(groups.get(&true), Some(&vec![4, 10, 8]));
+    assert_eq!(groups.get(&false), Some(&vec![3, 7, 1]));
+    assert_eq!(groups.len(), 2);
+}
+
+#[test]
+fn replace_all_swaps_in_the_new_key_set() {
+    let mut map = populated(0..10);
+    map.replace_all((100..105).map(|key| (key, key)));
+    assert_eq!(map.len(), 5);
+    assert!((0..10).all(|key| map.get_cloned(&key).is_none()));
+    assert!((100..105).all(|key| map.get_cloned(&key) == Some(key)));
+    assert_eq!(map.capacity(), 64);
+}
+
+#[test]
+fn last_resize_moved_counts_entries_present_before_the_resize() {
+    let mut map = RobinHashMap::new(20);
+    assert_eq!(map.last_resize_moved(), 0);
+    let threshold = map.resize_threshold() as u32;
+    for key in 0..=threshold {
+        map.insert(key, key);
+    }
+    assert!(map.capacity() > 20);
+    assert_eq!(map.last_resize_moved(), threshold as usize);
+}
+
+#[test]
+fn with_value_maps_present_values_only() {
+    let mut map = RobinHashMap::new(8);
+    map.insert(1, "four");
+    assert_eq!(map.with_value(&1, |value| value.len()), Some(4));
+    assert_eq!(map.with_value(&2, |value| value.len()), None);
+}
+
+#[test]
+fn weighted_probe_cost_follows_the_hot_keys() {
+    let mut map = RobinHashMap::new(16);
+    for id in 0..3 {
+        map.insert(Colliding(id), id);
+    }
+    // Colliding(0) sits at home (1 probe), Colliding(2) two slots on (3 probes)
+    let cost = map.weighted_probe_cost(&[(Colliding(0), 1), (Colliding(2), 9)]);
+    assert!((cost - 2.8).abs() < 1e-9);
+    assert_eq!(map.weighted_probe_cost(&[]), 0.0);
+}
+
+#[test]
+fn from_prehashed_places_keys_where_lookups_find_them() {
+    let keys: Vec<u32> = (0..50).collect();
+    let hashes = RobinHashMap::<u32, u32>::prehash(&keys);
+    let map = RobinHashMap::from_prehashed(
+        hashes
+            .into_iter()
+            .zip(keys)
+            .map(|(hash, key)| (hash, key, key + 1)),
+    );
+    assert_eq!(map.len(), 50);
+    for key in 0..50 {
+        assert_eq!(map.get_cloned(&key), Some(key + 1));
+    }
+}
+
+#[test]
+fn occupancy_rle_encodes_a_crafted_layout() {
+    let mut array = vec![None; 6];
+    array[1] = bucket(1u32, 1u32, 1);
+    array[2] = bucket(2, 2, 1);
+    array[4] = bucket(4, 4, 1);
+    let map = from_raw(array);
+    assert_eq!(
+        map.occupancy_rle(),
+        vec![(false, 1), (true, 2), (false, 1), (true, 1), (false, 1)]
+    );
+}
+
+#[test]
+fn merge_max_keeps_the_value_the_comparator_ranks_higher() {
+    let mut map = RobinHashMap::new(16);
+    map.insert(1u32, 5i32);
+    map.insert(2, -9);
+    let mut other = RobinHashMap::new(16);
+    other.insert(1, -7);
+    other.insert(2, 3);
+    other.insert(3, 1);
+    // Rank by magnitude rather than sign
+    map.merge_max(other, |a, b| a.abs().cmp(&b.abs()));
+    assert_eq!(map.get_cloned(&1), Some(-7));
+    assert_eq!(map.get_cloned(&2), Some(-9));
+    assert_eq!(map.get_cloned(&3), Some(1));
+}
+
+#[test]
+fn colliding_keys_with_reports_keys_sharing_a_home_slot() {
+    let mut ours = RobinHashMap::new(16);
+    let mut theirs = RobinHashMap::new(32);
+    ours.insert(Colliding(1), 1);
+    ours.insert(Colliding(2), 2);
+    theirs.insert(Colliding(9), 9);
+    let mut colliding = ours.colliding_keys_with(&theirs);
+    colliding.sort();
+    assert_eq!(colliding, vec![&Colliding(1), &Colliding(2)]);
+    assert!(ours.colliding_keys_with(&RobinHashMap::new(32)).is_empty());
+}
+
+#[test]
+fn into_iter_by_distance_yields_every_entry_nearest_first() {
+    let mut map = RobinHashMap::new(32);
+    for id in 0..5 {
+        map.insert(Colliding(id), id);
+    }
+    let distances: std::collections::HashMap<Colliding, usize> = map
+        .raw_iter()
+        .filter_map(|slot| match slot {
+            SlotView::Occupied(key, _, distance) => Some((*key, distance)),
+            _ => None,
+        })
+        .collect();
+    let yielded: Vec<usize> = map
+        .into_iter_by_distance()
+        .map(|(key, _)| distances[&key])
+        .collect();
+    assert_eq!(yielded, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn verify_after_resize_passes_after_a_resize_and_flags_a_misplaced_entry() {
+    let mut map = populated(0..40);
+    map.rebuild(200);
+    assert_eq!(map.verify_after_resize(), Ok(()));
+
+    let home = colliding_home(8);
+    let mut array = vec![None; 8];
+    array[(home + 2) % 8] = bucket(Colliding(1), 1, 1);
+    assert!(from_raw(array).verify_after_resize().is_err());
+}
+
+#[test]
+fn to_dot_draws_a_node_per_entry_and_an_edge_per_displacement() {
+    let mut map = RobinHashMap::new(16);
+    for id in 0..3 {
+        map.insert(Colliding(id), id);
+    }
+    let home = colliding_home(16);
+    let dot = map.to_dot();
+    assert!(dot.starts_with("digraph"));
+    assert_eq!(
+        dot.lines().filter(|line| line.contains(" [label=")).count(),
+        5
+    );
+    assert_eq!(dot.lines().filter(|line| line.contains("->")).count(), 2);
+    for offset in 0..3 {
+        assert!(dot.contains(&format!("slot{} [label=", (home + offset) % 16)));
+    }
+    for offset in 1..3 {
+        assert!(dot.contains(&format!("slot{} -> slot{}", home, (home + offset) % 16)));
+    }
+}
+
+#[test]
+fn insert_batch_classifies_each_pair() {
+    let mut map = populated(0..3);
+    let outcomes = map.insert_batch([(1, 11), (5, 50), (2, 22), (5, 55)]);
+    assert_eq!(
+        outcomes,
+        vec![
+            InsertOutcome::Updated(10),
+            InsertOutcome::Inserted,
+            InsertOutcome::Updated(20),
+            InsertOutcome::Updated(50),
+        ]
+    );
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.get_cloned(&5), Some(55));
+}
+
+#[test]
+fn contains_by_matches_on_a_single_field() {
+    let keys: Vec<(u32, String)> = (0..10).map(|id| (id, format!("payload-{}", id))).collect();
+    let mut map = RobinHashMap::new(32);
+    for key in &keys {
+        map.insert(key.clone(), ());
+    }
+    let hashes = RobinHashMap::<(u32, String), ()>::prehash(&keys);
+    for (hash, (id, _)) in hashes.iter().zip(&keys) {
+        assert!(map.contains_by(*hash, |key| key.0 == *id));
+        assert!(!map.contains_by(*hash, |key| key.0 == id + 100));
+    }
+}
+
+#[test]
+fn resizes_for_matches_an_actual_run() {
+    for (initial, inserts) in [(0, 10), (1, 1), (7, 100), (64, 40), (64, 1000)] {
+        let mut map = RobinHashMap::new(initial).with_stats();
+        for key in 0..inserts as u32 {
+            map.insert(key, key);
+        }
+        let predicted =
+            RobinHashMap::<u32, u32>::resizes_for(initial, inserts, GROWTH_FACTOR, MAX_LOAD_FACTOR);
+        assert_eq!(
+            predicted,
+            map.lifetime_stats().resize_count,
+            "{} into {}",
+            inserts,
+            initial
+        );
+    }
+}
+
+#[test]
+fn home_hit_ratio_separates_good_and_constant_hashes() {
+    let mut spread = RobinHashMap::new(1024);
+    let mut clustered = RobinHashMap::new(1024);
+    for id in 0..50u32 {
+        spread.insert(id, id);
+        clustered.insert(Colliding(id), id);
+    }
+    assert!(spread.home_hit_ratio() > 0.9);
+    assert!((clustered.home_hit_ratio() - 1.0 / 50.0).abs() < 1e-9);
+    assert_eq!(RobinHashMap::<u32, u32>::new(4).home_hit_ratio(), 0.0);
+}
+
+#[test]
+fn cluster_of_returns_the_whole_cluster_from_any_member() {
+    let mut map = RobinHashMap::new(32);
+    for id in 0..4 {
+        map.insert(Colliding(id), id);
+    }
+    for id in 0..4 {
+        let mut members: Vec<u32> = map
+            .cluster_of(&Colliding(id))
+            .into_iter()
+            .map(|(_, &v)| v)
+            .collect();
+        members.sort();
+        assert_eq!(members, vec![0, 1, 2, 3]);
+    }
+    // An absent key reports the cluster at its home slot
+    assert_eq!(map.cluster_of(&Colliding(9)).len(), 4);
+    assert!(RobinHashMap::<u32, u32>::new(8).cluster_of(&1).is_empty());
+}
+
+#[test]
+fn peak_len_survives_deletions() {
+    let mut map = RobinHashMap::new(16);
+    for key in 0..40u32 {
+        map.insert(key, key);
+    }
+    for key in 0..30u32 {
+        map.delete(&key);
+    }
+    assert_eq!(map.len(), 10);
+    assert_eq!(map.peak_len(), 40);
+    map.insert(100, 100);
+    assert_eq!(map.peak_len(), 40);
+}
+
+#[test]
+fn get_closest_finds_the_nearest_key() {
+    let mut map = RobinHashMap::new(16);
+    for key in [10, 20, 30] {
+        map.insert(key, key * 10);
+    }
+    assert_eq!(map.get_closest(&22), Some((&20, &200)));
+    assert_eq!(map.get_closest(&30), Some((&30, &300)));
+    // Equidistant keys resolve to the lower one
+    assert_eq!(map.get_closest(&25), Some((&20, &200)));
+    assert_eq!(RobinHashMap::<i32, i32>::new(4).get_closest(&1), None);
+}
+
+#[test]
+fn get_closest_does_not_overflow_at_integer_extremes() {
+    let mut map = RobinHashMap::new(16);
+    map.insert(i32::MIN, 1);
+    map.insert(i32::MAX, 2);
+    assert_eq!(map.get_closest(&(i32::MIN + 1)), Some((&i32::MIN, &1)));
+    assert_eq!(map.get_closest(&(i32::MAX - 1)), Some((&i32::MAX, &2)));
+
+    let mut wide = RobinHashMap::new(16);
+    wide.insert(u128::MAX, 1);
+    wide.insert(0u128, 2);
+    assert_eq!(wide.get_closest(&(u128::MAX - 5)), Some((&u128::MAX, &1)));
+}
+
+#[test]
+fn value_size_histogram_bins_by_inclusive_upper_bound() {
+    let mut map = RobinHashMap::new(16);
+    for (key, value) in ["", "a", "abcd", "abcde", "abcdefghij", "abcdefghijklmnop"]
+        .into_iter()
+        .enumerate()
+    {
+        map.insert(key, value);
+    }
+    // Bins: <= 1, <= 4, <= 10, and anything larger
+    assert_eq!(
+        map.value_size_histogram(|value| value.len(), &[1, 4, 10]),
+        vec![2, 1, 2, 1]
+    );
+    assert_eq!(map.value_size_histogram(|value| value.len(), &[]), vec![6]);
+}
+
+#[test]
+fn fold_visits_every_live_entry() {
+    let map = populated(0..50);
+    assert_eq!(
+        map.fold(0, |sum, _, &value| sum + value),
+        (0..50).sum::<u32>() * 10
+    );
+    assert_eq!(map.fold(0, |count, _, _| count + 1), 50);
+    assert_eq!(
+        RobinHashMap::<u32, u32>::new(4).fold(7, |sum, _, &value| sum + value),
+        7
+    );
+}
+
+#[test]
+fn lookups_terminate_with_an_inconsistent_max_psl() {
+    // A full table whose recorded lengths never stop the probe early, plus a max_psl far past the capacity
+    let mut map = from_raw((0..4).map(|id| bucket(Colliding(id), id, 100)).collect());
+    map.max_psl = usize::MAX;
+    assert_eq!(map.get(&Colliding(9)), None);
+    assert!(!map.contains(&Colliding(9)));
+    assert_eq!(map.delete(&Colliding(9)), None);
+    assert_eq!(map.get(&Colliding(2)), Some(&2));
+}
+
+#[test]
+fn summary_reports_len_capacity_and_probe_lengths() {
+    let map = from_raw(vec![bucket(1, 1, 1), bucket(2, 2, 2), None, None]);
+    assert_eq!(
+        map.summary(),
+        "RobinHashMap len=2 capacity=4 load=0.50 max_psl=2 avg_probe=1.50"
+    );
+}
+
+#[test]
+fn keys_affected_by_shrink_matches_an_actual_rehash() {
+    let mut map = populated(0..40);
+    let homes = |map: &RobinHashMap<u32, u32>| -> Vec<(u32, usize)> {
+        let mut homes: Vec<_> = map
+            .entries()
+            .map(|(&key, _)| (key, map.home_slot(hash_with_fxhash(&key))))
+            .collect();
+        homes.sort();
+        homes
+    };
+    let estimate = map.keys_affected_by_shrink(48);
+    let before = homes(&map);
+    map.rebuild(48);
+    let moved = before
+        .iter()
+        .zip(homes(&map))
+        .filter(|(old, new)| old.1 != new.1)
+        .count();
+    assert_eq!(estimate, moved);
+    assert!(moved > 0);
+    assert_eq!(map.keys_affected_by_shrink(48), 0);
+}
+
+#[test]
+fn from_iter_with_merges_duplicate_keys() {
+    let map = RobinHashMap::from_iter_with(
+        [("a", 1), ("b", 2), ("a", 3), ("c", 4), ("a", 5), ("b", 6)],
+        |existing, incoming| existing + incoming,
+    );
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.get_cloned(&"a"), Some(9));
+    assert_eq!(map.get_cloned(&"b"), Some(8));
+    assert_eq!(map.get_cloned(&"c"), Some(4));
+}
+
+#[test]
+fn hash_of_matches_prehash_and_ignores_the_salt() {
+    let mut map = populated(0..20);
+    let before = map.hash_of(&7);
+    assert_eq!(before, map.hash_of(&7));
+    assert_eq!(before, RobinHashMap::<u32, u32>::prehash(&[7])[0]);
+    map.reseed(0x5eed);
+    assert_eq!(map.hash_of(&7), before);
+    assert_eq!(map.get_prehashed(map.hash_of(&7), &7), Some(&70));
+}
+
+#[test]
+fn hash_of_differs_between_hashers() {
+    let robin = populated(0..4);
+    let open = crate::basic_hash_map::OpenHashMap::<u32, u32>::new(16);
+    assert_ne!(robin.hash_of(&3), open.hash_of(&3));
+    assert_eq!(open.hash_of(&3), open.hash_of(&3));
+}
+
+#[test]
+fn get_each_mut_returns_none_for_absent_and_repeated_keys() {
+    let mut map = populated(0..10);
+    let mut values = map.get_each_mut(&[3, 99, 5, 3, 7]);
+    let pattern: Vec<bool> = values.iter().map(Option::is_some).collect();
+    assert_eq!(pattern, vec![true, false, true, false, true]);
+    for value in values.iter_mut().flatten() {
+        **value += 1;
+    }
+    assert_eq!(map.get_cloned(&3), Some(31));
+    assert_eq!(map.get_cloned(&5), Some(51));
+    assert_eq!(map.get_cloned(&7), Some(71));
+    assert_eq!(map.get_cloned(&4), Some(40));
+}
+
+#[test]
+fn diagnostics_are_internally_consistent() {
+    let mut map = populated(0..40);
+    map.delete(&3);
+    let report = map.diagnostics();
+    assert_eq!(report.len, 39);
+    assert_eq!(report.capacity, map.capacity());
+    assert_eq!(
+        report.displacement_histogram.iter().sum::<usize>(),
+        report.len
+    );
+    assert_eq!(report.displacement_histogram.len(), map.max_psl);
+    assert_eq!(report.tombstones, 0);
+    assert!((report.load_factor - 39.0 / map.capacity() as f64).abs() < 1e-9);
+    assert!(report.is_consistent(), "{:?}", report.violations);
+}
+
+#[test]
+fn diagnostics_report_a_corrupted_layout() {
+    // The second entry claims a probe length its slot cannot have
+    let home = colliding_home(8);
+    let mut array = vec![None; 8];
+    array[home] = bucket(Colliding(0), 0, 1);
+    array[(home + 1) % 8] = bucket(Colliding(1), 1, 5);
+    assert!(!from_raw(array).diagnostics().is_consistent());
+}
+
+#[test]
+fn map_keys_reinserts_under_transformed_keys() {
+    let strings = populated(0..30).map_keys(|key| key.to_string());
+    assert_eq!(strings.len(), 30);
+    for key in 0..30u32 {
+        assert_eq!(strings.get(&key.to_string()), Some(&(key * 10)));
+    }
+    // A transform that collides keys keeps one of the colliding values
+    let folded = populated(0..30).map_keys(|key| key % 3);
+    assert_eq!(folded.len(), 3);
+    for key in 0..3 {
+        assert!(folded.get(&key).is_some_and(|value| value % 30 == key * 10));
+    }
+}
+
+#[test]
+fn probe_length_percentile_uses_nearest_rank() {
+    // 50 entries at probe length 1, 45 at 2, 4 at 3, and 1 at 4
+    let lengths = [(1, 50), (2, 45), (3, 4), (4, 1)];
+    let array: Vec<_> = lengths
+        .iter()
+        .flat_map(|&(length, count)| std::iter::repeat_n(length, count))
+        .enumerate()
+        .map(|(key, length)| bucket(key as u32, 0u32, length))
+        .collect();
+    let map = from_raw(array);
+    assert_eq!(map.probe_length_percentile(0.5), 1);
+    assert_eq!(map.probe_length_percentile(0.51), 2);
+    assert_eq!(map.probe_length_percentile(0.99), 3);
+    assert_eq!(map.probe_length_percentile(1.0), 4);
+    assert_eq!(map.probe_length_percentile(0.0), 1);
+    assert_eq!(
+        RobinHashMap::<u32, u32>::new(4).probe_length_percentile(0.99),
+        0
+    );
+}
+
+#[test]
+fn increment_accumulates_in_a_single_probe_walk() {
+    let mut map = RobinHashMap::new(64).with_stats();
+    for id in 0..3 {
+        map.insert(Colliding(id), 0u32);
+    }
+    let before = map.lifetime_stats().total_probes;
+    // A miss walks past the three chain members to the free slot behind them, and no further
+    assert_eq!(*map.increment(Colliding(3), 5), 5);
+    assert_eq!(map.lifetime_stats().total_probes - before, 4);
+    assert_eq!(*map.increment(Colliding(3), 3), 8);
+    assert_eq!(*map.increment(Colliding(3), 2), 10);
+    assert_eq!(map.lifetime_stats().total_probes - before, 12);
+    assert_eq!(map.len(), 4);
+}
+
+#[test]
+fn increment_grows_the_table_as_keys_arrive() {
+    let mut map = RobinHashMap::new(2);
+    for round in 0..3u32 {
+        for key in 0..100u32 {
+            map.increment(key, key + round);
+        }
+    }
+    assert_eq!(map.len(), 100);
+    for key in 0..100u32 {
+        assert_eq!(map.get_cloned(&key), Some(3 * key + 3));
+    }
+}
+
+#[test]
+fn balance_ratio_detects_a_biased_hasher() {
+    let mut spread = RobinHashMap::new(4096);
+    for key in 0..1000u32 {
+        spread.insert(key, key);
+    }
+    let ratio = spread.balance_ratio();
+    assert!((0.8..1.25).contains(&ratio), "{ratio}");
+
+    // Every colliding key shares one home, so one half of the table gets everything
+    let mut biased = RobinHashMap::new(64);
+    for id in 0..20 {
+        biased.insert(Colliding(id), id);
+    }
+    let expected = if colliding_home(64) < 32 {
+        f64::INFINITY
+    } else {
+        0.0
+    };
+    assert_eq!(biased.balance_ratio(), expected);
+    assert_eq!(RobinHashMap::<u32, u32>::new(8).balance_ratio(), 1.0);
+}
+
+#[test]
+fn get_cloned_returns_an_owned_value() {
+    let mut map = RobinHashMap::new(16);
+    map.insert(1, String::from("one"));
+    let owned: Option<String> = map.get_cloned(&1);
+    assert_eq!(owned.as_deref(), Some("one"));
+    drop(map);
+    assert_eq!(owned, Some(String::from("one")));
+    assert_eq!(RobinHashMap::<u32, String>::new(4).get_cloned(&1), None);
+}
+
+#[test]
+fn reserve_cache_aligned_rounds_capacity_to_the_alignment_unit() {
+    let unit = RobinHashMap::<u32, u32>::cache_alignment_unit();
+    assert!(unit > 1);
+    let mut map = populated(0..10);
+    map.reserve_cache_aligned(200);
+    assert!(map.capacity() >= capacity_for(210));
+    assert!(map.capacity().is_multiple_of(unit));
+
+    // An unaligned table with room to spare is still realigned
+    let mut odd = RobinHashMap::<u32, u32>::new(unit * 3 + 1);
+    odd.insert(1, 1);
+    odd.reserve_cache_aligned(0);
+    assert_eq!(odd.capacity(), unit * 4);
+    assert_eq!(odd.get_cloned(&1), Some(1));
+}
+
+#[test]
+fn intern_values_shares_equal_values() {
+    let colours = ["red".repeat(100), "green".repeat(100), "blue".repeat(100)];
+    let mut map = RobinHashMap::new(64);
+    for key in 0..30u32 {
+        // A fresh allocation per key, so nothing is shared before interning
+        map.insert(key, Rc::new(colours[key as usize % 3].clone()));
+    }
+    assert!(map.entries().all(|(_, value)| Rc::strong_count(value) == 1));
+
+    assert_eq!(map.intern_values(), 3);
+    for (key, value) in map.entries() {
+        assert_eq!(**value, colours[*key as usize % 3]);
+        assert_eq!(Rc::strong_count(value), 10);
+    }
+    assert!(Rc::ptr_eq(map.get(&0).unwrap(), map.get(&3).unwrap()));
+}
+
+#[test]
+fn iter_shuffled_is_deterministic_per_seed_and_complete() {
+    let map = populated(0..40);
+    let order = |seed| -> Vec<u32> { map.iter_shuffled(seed).map(|(&key, _)| key).collect() };
+    assert_eq!(order(7), order(7));
+    assert_ne!(order(7), order(8));
+    for seed in [7, 8] {
+        let mut keys = order(seed);
+        keys.sort();
+        assert_eq!(keys, (0..40).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn key_jaccard_compares_key_sets() {
+    let left = populated(0..20);
+    // Shares 10 keys with left: intersection 10, union 30
+    let right = populated(10..30);
+    assert!((left.key_jaccard(&right) - 1.0 / 3.0).abs() < 1e-9);
+    assert_eq!(left.key_jaccard(&right), right.key_jaccard(&left));
+    assert_eq!(left.key_jaccard(&populated(0..20)), 1.0);
+    assert_eq!(left.key_jaccard(&populated(100..120)), 0.0);
+    assert_eq!(
+        RobinHashMap::<u32, u32>::new(4).key_jaccard(&RobinHashMap::new(8)),
+        1.0
+    );
+}
+
+#[test]
+fn prefetch_does_not_affect_lookups() {
+    let map = populated(0..20);
+    for key in 0..25u32 {
+        map.prefetch(&key);
+        assert_eq!(map.get_cloned(&key), (key < 20).then_some(key * 10));
+    }
+    RobinHashMap::<u32, u32>::new(0).prefetch(&1);
+}
+
+#[test]
+fn retain_only_keys_intersects_with_exported_keys() {
+    let server = populated(0..20);
+    let mut client = populated(10..30);
+    let mut exported = server.export_keys();
+    exported.sort();
+    assert_eq!(exported, (0..20).collect::<Vec<_>>());
+
+    client.retain_only_keys(&exported);
+    let mut kept = client.export_keys();
+    kept.sort();
+    assert_eq!(kept, (10..20).collect::<Vec<_>>());
+    assert_eq!(client.len(), 10);
+    assert_eq!(client.get_cloned(&15), Some(150));
+    assert_eq!(client.get_cloned(&25), None);
+}
+
+#[test]
+fn shrink_savings_bytes_matches_an_actual_shrink() {
+    let mut map = populated(0..40);
+    for key in 0..35 {
+        map.delete(&key);
+    }
+    let estimate = map.shrink_savings_bytes();
+    assert!(estimate > 0);
+    let before = map.capacity();
+    map.rebuild(capacity_for(map.len()));
+    assert_eq!(
+        estimate,
+        (before - map.capacity()) * std::mem::size_of::<Option<Bucket<u32, u32>>>()
+    );
+    assert_eq!(map.shrink_savings_bytes(), 0);
+}
+
+#[test]
+fn to_canonical_ignores_insertion_order() {
+    let mut forward = RobinHashMap::new(8);
+    let mut backward = RobinHashMap::new(64);
+    for key in 0..30u32 {
+        forward.insert(key, key * 10);
+        backward.insert(29 - key, (29 - key) * 10);
+    }
+    let canonical = forward.to_canonical();
+    assert_eq!(canonical, backward.to_canonical());
+    assert_eq!(
+        canonical,
+        (0..30).map(|key| (key, key * 10)).collect::<Vec<_>>()
+    );
+
+    // Rebuilding from either order lands every entry in the same slot
+    let mut shuffled = canonical.clone();
+    shuffled.reverse();
+    let rebuilt = RobinHashMap::from_canonical(canonical);
+    let reversed = RobinHashMap::from_canonical(shuffled);
+    assert_eq!(rebuilt.capacity(), reversed.capacity());
+    for key in 0..30u32 {
+        assert_eq!(rebuilt.get_with_index(&key), reversed.get_with_index(&key));
+    }
+}
+
+#[test]
+fn occupied_runs_follow_the_crafted_clusters() {
+    // Occupied: 0 | 2 3 4 | 7; slots 1, 5 and 6 are empty
+    let mut array = vec![None; 8];
+    for (index, key) in [(0, 10), (2, 20), (3, 30), (4, 40), (7, 70)] {
+        array[index] = bucket(key, key, 1);
+    }
+    let map = from_raw(array);
+    let runs: Vec<(usize, Vec<u32>)> = map
+        .occupied_runs()
+        .map(|(start, entries)| (start, entries.into_iter().map(|(&key, _)| key).collect()))
+        .collect();
+    assert_eq!(
+        runs,
+        vec![(0, vec![10]), (2, vec![20, 30, 40]), (7, vec![70])]
+    );
+    assert_eq!(RobinHashMap::<u32, u32>::new(4).occupied_runs().count(), 0);
+}
+
+#[test]
+fn throughput_curve_degrades_as_load_rises() {
+    for seed in [1, 2, 3] {
+        let curve = RobinHashMap::throughput_curve(4096, seed, 9);
+        assert_eq!(curve.len(), 9);
+        assert!(curve.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(
+            curve.windows(2).all(|pair| pair[0].1 <= pair[1].1),
+            "{curve:?}"
+        );
+        let (last_load, last_probe) = curve[8];
+        assert!((last_load - MAX_LOAD_FACTOR).abs() < 0.01);
+        assert!(curve[0].1 >= 1.0 && last_probe > curve[0].1);
+    }
+    assert!(RobinHashMap::throughput_curve(0, 1, 4).is_empty());
+}
+
+#[test]
+fn get_or_compute_runs_once_per_key_in_a_single_probe_walk() {
+    let mut map = RobinHashMap::new(64).with_stats();
+    let mut calls = 0;
+    for _ in 0..3 {
+        for key in 0..5u32 {
+            assert_eq!(
+                *map.get_or_compute(key, || {
+                    calls += 1;
+                    key * 2
+                }),
+                key * 2
+            );
+        }
+    }
+    assert_eq!(calls, 5);
+    assert_eq!(map.len(), 5);
+
+    let mut chained = RobinHashMap::new(64).with_stats();
+    for id in 0..2 {
+        chained.insert(Colliding(id), 0);
+    }
+    let before = chained.lifetime_stats().total_probes;
+    // Both the miss and the hit stop at the third slot of the chain
+    chained.get_or_compute(Colliding(2), || 7);
+    chained.get_or_compute(Colliding(2), || unreachable!());
+    assert_eq!(chained.lifetime_stats().total_probes - before, 6);
+}
+
+#[test]
+fn predict_worst_probes_after_resize_matches_the_resize() {
+    for new_capacity in [40, 45, 64, 200] {
+        let mut map = populated(0..36);
+        for id in 0..4 {
+            map.delete(&id);
+        }
+        let predicted = map.predict_worst_probes_after_resize(new_capacity);
+        map.rebuild(new_capacity);
+        assert_eq!(predicted, map.max_psl, "capacity {new_capacity}");
+    }
+}
+
+#[test]
+fn retain_counting_reports_removed_entries() {
+    let mut map = populated(0..30);
+    let before = map.len();
+    let removed = map.retain_counting(|key, _| key % 3 == 0);
+    assert_eq!(removed, before - map.len());
+    assert_eq!(removed, 20);
+    assert_eq!(map.get_cloned(&9), Some(90));
+    assert_eq!(map.get_cloned(&10), None);
+    assert_eq!(map.retain_counting(|_, _| true), 0);
+}
+
+#[test]
+fn entry_access_counts_follow_the_read_pattern() {
+    let mut map = RobinHashMap::new(16).with_stats();
+    for id in 0..4 {
+        map.insert(Colliding(id), id);
+    }
+    for (id, reads) in [(0, 3), (1, 1), (3, 5)] {
+        for _ in 0..reads {
+            map.get(&Colliding(id));
+        }
+    }
+    map.get(&Colliding(9));
+    let counts = |map: &RobinHashMap<Colliding, u32>| {
+        let mut counts: Vec<(u32, usize)> = map
+            .entry_access_counts()
+            .into_iter()
+            .map(|(key, reads)| (key.0, reads))
+            .collect();
+        counts.sort();
+        counts
+    };
+    // Equal hashes do not merge the counts of distinct keys
+    assert_eq!(counts(&map), vec![(0, 3), (1, 1), (2, 0), (3, 5)]);
+
+    // Counts move with their entries through a resize and a reseed
+    map.rebuild(64);
+    map.reseed(7);
+    assert_eq!(counts(&map), vec![(0, 3), (1, 1), (2, 0), (3, 5)]);
+
+    // A deleted key starts over when it comes back
+    map.delete(&Colliding(3));
+    map.insert(Colliding(3), 3);
+    assert_eq!(counts(&map)[3], (3, 0));
+}
+
+#[test]
+fn entry_access_counts_stay_zero_without_stats() {
+    let map = populated(0..4);
+    map.get(&1);
+    assert!(
+        map.entry_access_counts()
+            .iter()
+            .all(|&(_, reads)| reads == 0)
+    );
+}
+
+#[test]
+fn union_with_combines_shared_keys_and_leaves_inputs_intact() {
+    let left = populated(0..10);
+    let mut right = populated(5..15);
+    for key in 5..15u32 {
+        right.insert(key, if key % 2 == 0 { 1 } else { 1000 });
+    }
+    let union = left.union_with(&right, |a, b| *a.max(b));
+    assert_eq!(union.len(), 15);
+    for key in 0..15u32 {
+        let expected = match key {
+            0..5 => key * 10,
+            5..10 => (key * 10).max(if key % 2 == 0 { 1 } else { 1000 }),
+            _ => {
+                if key % 2 == 0 {
+                    1
+                } else {
+                    1000
+                }
+            }
+        };
+        assert_eq!(union.get_cloned(&key), Some(expected), "key {key}");
+    }
+    assert_eq!(left.to_canonical(), populated(0..10).to_canonical());
+    assert_eq!(right.len(), 10);
+    assert_eq!(right.get_cloned(&6), Some(1));
+}
+
+#[test]
+fn collision_attack_is_detected_and_cleared_by_reseed() {
+    let mut constant = RobinHashMap::new(256);
+    let mut spread = RobinHashMap::new(256);
+    for id in 0..40u32 {
+        constant.insert(Colliding(id), id);
+        spread.insert(id, id);
+    }
+    assert!(constant.is_under_collision_attack(16));
+    assert!(!spread.is_under_collision_attack(16));
+
+    // Distinct hashes that were picked to share one home slot lose that alignment under a new salt
+    let mut flooded = RobinHashMap::new(256);
+    for key in keys_homed_at(256, 9, 40) {
+        flooded.insert(key, key);
+    }
+    assert!(flooded.is_under_collision_attack(16));
+    flooded.reseed(0x5eed);
+    assert!(!flooded.is_under_collision_attack(16));
+    assert_eq!(flooded.len(), 40);
+}
+
+#[test]
+fn clone_with_capacity_keeps_bloom_peak_len_and_generation() {
+    let mut map = RobinHashMap::new(64).with_bloom();
+    for key in 0..20u32 {
+        map.insert(key, key);
+    }
+    for key in 0..5u32 {
+        map.delete(&key);
+    }
+    let copy = map.clone_with_capacity(256);
+    assert!(copy.bloom.is_some());
+    assert_eq!(copy.peak_len(), 20);
+    // The copy continues the original's generation count; its rebuild is one more layout change
+    assert_eq!(copy.generation, map.generation + 1);
+    for key in 0..20u32 {
+        assert_eq!(copy.get_cloned(&key), (key >= 5).then_some(key));
+    }
+}
+
+#[test]
+fn frozen_capacity_refuses_new_keys_past_the_threshold() {
+    let mut map = RobinHashMap::new(16);
+    let threshold = map.resize_threshold() as u32;
+    {
+        let mut frozen = map.freeze_capacity();
+        for key in 0..threshold {
+            assert_eq!(frozen.insert(key, key), Ok(InsertOutcome::Inserted));
+        }
+        assert_eq!(
+            frozen.insert(threshold, 0),
+            Err(TableFull {
+                key: threshold,
+                value: 0
+            })
+        );
+        // Updating a present key needs no room and still goes through
+        assert_eq!(frozen.insert(0, 7), Ok(InsertOutcome::Updated(0)));
+        assert_eq!(frozen.capacity(), 16);
+        assert_eq!(frozen.len(), threshold as usize);
+    }
+    // With the guard gone the same insert grows the table as usual
+    map.insert(threshold, 0);
+    assert!(map.capacity() > 16);
+    assert_eq!(map.get_cloned(&0), Some(7));
+    assert_eq!(map.get_cloned(&threshold), Some(0));
+}
+
+#[test]
+fn into_perfect_resolves_every_key_on_the_first_probe() {
+    let mut map = populated(0..500);
+    for key in (0..500).step_by(7) {
+        map.delete(&key);
+    }
+    let expected = map.len();
+    let perfect = map.into_perfect();
+    assert_eq!(perfect.len(), expected);
+    for key in 0..500 {
+        if key % 7 == 0 {
+            assert_eq!(perfect.get(&key), None);
+        } else {
+            assert_eq!(perfect.get(&key), Some(&(key * 10)));
+            assert_eq!(perfect.probe_count(&key), 1);
+        }
+    }
+}
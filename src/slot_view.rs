@@ -0,0 +1,7 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotView<'a, K, V> {
+    Empty,
+    Deleted,
+    // key, value and displacement from the home slot
+    Occupied(&'a K, &'a V, usize),
+}
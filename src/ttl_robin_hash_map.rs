@@ -0,0 +1,139 @@
+use crate::robin_hood_hash_map::RobinHashMap;
+use crate::visit_action::VisitAction;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// Expired entries stay in the table until overwritten, deleted, or purged; lookups just skip them
+#[derive(Debug, Clone)]
+pub struct TtlRobinHashMap<K, V> {
+    inner: RobinHashMap<K, (V, Instant)>,
+    default_ttl: Duration,
+    clock: fn() -> Instant,
+}
+
+impl<K, V> TtlRobinHashMap<K, V>
+where
+    K: Eq + Clone + Hash,
+    V: Clone,
+{
+    pub fn with_ttl(capacity: usize, default_ttl: Duration) -> Self {
+        Self::with_clock(capacity, default_ttl, Instant::now)
+    }
+
+    pub fn with_clock(capacity: usize, default_ttl: Duration, clock: fn() -> Instant) -> Self {
+        TtlRobinHashMap {
+            inner: RobinHashMap::new(capacity),
+            default_ttl,
+            clock,
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_ttl(key, value, self.default_ttl)
+    }
+
+    pub fn insert_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let now = (self.clock)();
+        let entry = (value, now + ttl);
+        match self.inner.get_mut(&key) {
+            Some(existing) => {
+                let (old, expires_at) = std::mem::replace(existing, entry);
+                (expires_at > now).then_some(old)
+            }
+            None => {
+                self.inner.insert(key, entry);
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let now = (self.clock)();
+        self.inner
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(value, _)| value)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        let now = (self.clock)();
+        self.inner
+            .delete(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(value, _)| value)
+    }
+
+    pub fn purge_expired(&mut self) {
+        let now = (self.clock)();
+        self.inner.retain_visit(|_, (_, expires_at)| {
+            if *expires_at > now {
+                VisitAction::Keep
+            } else {
+                VisitAction::Remove
+            }
+        });
+    }
+
+    // Includes expired entries that have not been purged yet
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::OnceLock;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    thread_local! {
+        static ELAPSED: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    }
+
+    // Each test thread owns its elapsed time, so tests can advance it independently
+    fn fake_now() -> Instant {
+        *START.get_or_init(Instant::now) + ELAPSED.with(Cell::get)
+    }
+
+    fn advance(by: Duration) {
+        ELAPSED.with(|elapsed| elapsed.set(elapsed.get() + by));
+    }
+
+    #[test]
+    fn expired_entries_read_as_absent_and_are_purged() {
+        let mut map = TtlRobinHashMap::with_clock(16, Duration::from_secs(60), fake_now);
+        map.insert_ttl(1, "short", Duration::from_secs(1));
+        map.insert(2, "long");
+        assert_eq!(map.get(&1), Some(&"short"));
+
+        advance(Duration::from_secs(2));
+        assert_eq!(map.get(&1), None);
+        assert!(!map.contains(&1));
+        assert_eq!(map.get(&2), Some(&"long"));
+        assert_eq!(map.len(), 2);
+
+        map.purge_expired();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&2), Some(&"long"));
+    }
+
+    #[test]
+    fn overwriting_an_expired_entry_returns_nothing() {
+        let mut map = TtlRobinHashMap::with_clock(16, Duration::from_secs(1), fake_now);
+        map.insert(1, 10);
+        advance(Duration::from_secs(5));
+        assert_eq!(map.insert(1, 20), None);
+        assert_eq!(map.get(&1), Some(&20));
+        assert_eq!(map.delete(&1), Some(20));
+    }
+}
@@ -0,0 +1,6 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VisitAction {
+    Keep,
+    Remove,
+    Stop,
+}